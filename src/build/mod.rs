@@ -3,12 +3,23 @@ pub mod term_store;
 pub mod weights;
 
 use crate::{
+    inv_index::DEFAULT_GAP_THRESHOLD,
+    metadata::Metadata,
     term_store::TermIndexer,
     traits::{Decodable, Encodable},
-    vector_store, DocumentVector, Error, Index, Vector,
+    vector_store::{self, VectorEncoding},
+    DocumentVector, Error, Index, Vector,
 };
+use itertools::izip;
+#[cfg(feature = "rayon")]
+use rayon::iter::{IndexedParallelIterator, ParallelIterator};
 use serde::Serialize;
-use std::{collections::HashSet, io::Write};
+#[cfg(feature = "serde_json")]
+use std::io::{BufRead, BufReader, Read};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+};
 use term_store::TermStoreBuilder;
 
 use self::weights::TermWeight;
@@ -18,6 +29,13 @@ pub struct IndexBuilder<D> {
     vectors: Vec<DocumentVector<D>>,
     terms: TermStoreBuilder,
     term_weight: Option<Box<dyn TermWeight>>,
+    store_term_text: bool,
+    encoding: VectorEncoding,
+    gap_threshold: u32,
+    external_ids: Vec<Option<u64>>,
+    dedup: bool,
+    strict: bool,
+    store_term_freqs: bool,
     output_filter:
         Option<Box<dyn Fn(DocumentVector<D>, &TermIndexer) -> Option<DocumentVector<D>> + 'static>>,
 }
@@ -30,15 +48,107 @@ impl<D> IndexBuilder<D> {
             vectors: vec![],
             terms: TermStoreBuilder::new(),
             term_weight: None,
+            store_term_text: true,
+            encoding: VectorEncoding::Packed,
+            gap_threshold: DEFAULT_GAP_THRESHOLD,
+            external_ids: vec![],
+            dedup: false,
+            strict: false,
+            store_term_freqs: false,
             output_filter: None,
         }
     }
 
+    /// Creates a new Indexer from a JSON Lines stream. `extract` gets called for each line and
+    /// returns the document and its terms which will be inserted using `insert_new_vec`. Empty
+    /// lines are skipped
+    #[cfg(feature = "serde_json")]
+    pub fn from_jsonl<R, F>(reader: R, extract: F) -> Result<Self, Error>
+    where
+        R: Read,
+        F: Fn(&serde_json::Value) -> (D, Vec<String>),
+    {
+        let mut builder = Self::new();
+
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let value: serde_json::Value = serde_json::from_str(&line)?;
+            let (doc, terms) = extract(&value);
+            builder.insert_new_vec(doc, &terms);
+        }
+
+        Ok(builder)
+    }
+
     pub fn with_weight<U: TermWeight + 'static>(mut self, weight: U) -> Self {
         self.term_weight = Some(Box::new(weight));
         self
     }
 
+    /// Sets whether the text of the terms gets stored in the index (default: `true`). Without the
+    /// text only the document frequencies of the terms are stored, which makes the index smaller.
+    /// However terms can't be looked up by their text anymore, so building vectors from terms,
+    /// suggestions and resolving dimensions back into terms won't work. Dimension based
+    /// retrieval of documents still works
+    pub fn with_term_text(mut self, store_term_text: bool) -> Self {
+        self.store_term_text = store_term_text;
+        self
+    }
+
+    /// Stores the weights of all vectors quantized to 8 bits. This shrinks the vector store
+    /// considerably but similarities become approximations. See `DocumentVector::encode_quantized`
+    pub fn with_quantization(mut self, quantize: bool) -> Self {
+        self.encoding = if quantize {
+            VectorEncoding::Quantized
+        } else {
+            VectorEncoding::Packed
+        };
+        self
+    }
+
+    /// Sets the format the vectors get stored in. See `VectorEncoding`
+    pub fn with_encoding(mut self, encoding: VectorEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sets the largest gap between two used dimensions which gets filled with padding in the
+    /// inverted index. Larger gaps start a new run of dimensions stored with an explicit position,
+    /// which saves space for very sparse dimensions
+    pub fn with_gap_threshold(mut self, gap_threshold: u32) -> Self {
+        self.gap_threshold = gap_threshold;
+        self
+    }
+
+    /// Collapses documents with identical vectors into the first of them while building. The
+    /// vector store only contains the remaining documents, but the amount of documents each of
+    /// them represents can be retrieved using `VectorStore::multiplicity`. Document frequencies
+    /// and the total amount of documents still count all duplicates, so the inverse document
+    /// frequencies are the same as without deduplication. Only the document and external ID of
+    /// the first document are kept, the ones of the collapsed documents get dropped
+    pub fn with_dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Makes `build` fail with `Error::ZeroLengthVectors` if a vector has a length of zero after
+    /// applying the term weight. See `zero_length_vecs`
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Stores the frequency of each term within each document. This is required for changing
+    /// the term weight afterwards using `Index::reweight` but makes the index considerably bigger
+    pub fn with_term_freqs(mut self, store_term_freqs: bool) -> Self {
+        self.store_term_freqs = store_term_freqs;
+        self
+    }
+
     pub fn with_filter<F>(&mut self, filter: F)
     where
         F: Fn(DocumentVector<D>, &TermIndexer) -> Option<DocumentVector<D>> + 'static,
@@ -50,20 +160,95 @@ impl<D> IndexBuilder<D> {
     pub fn insert_new_vec<S: AsRef<str>>(&mut self, doc: D, terms: &[S]) -> usize {
         let doc_id = self.vectors.len();
 
+        let dimensions = self.add_doc_terms(doc_id, terms);
+
+        // Initialize with same weights for all of them
+        // We'll adjust the weigts later in `finish()`
+        let vec = Vector::create_new_raw(dimensions.into_iter().map(|i| (i, 1.0)).collect());
+        self.vectors.push(DocumentVector::new(doc, vec));
+        doc_id
+    }
+
+    /// Like `insert_new_vec` but additionally assigns `external_id` to the new vec, eg. a primary
+    /// key of a database. It is stored in the index and can be retrieved using
+    /// `Index::external_id`. Returns the ID of the new vec
+    pub fn insert_with_id<S: AsRef<str>>(
+        &mut self,
+        external_id: u64,
+        doc: D,
+        terms: &[S],
+    ) -> usize {
+        let doc_id = self.insert_new_vec(doc, terms);
+        self.external_ids.resize(doc_id, None);
+        self.external_ids.push(Some(external_id));
+        doc_id
+    }
+
+    /// Inserts all documents of `docs`. Counting the terms of each document happens in parallel,
+    /// assigning dimensions afterwards is done in the order of `docs`. The resulting index equals
+    /// one built by calling `insert_new_vec` for each document. `docs` has to be indexed so the
+    /// order of the documents is known
+    #[cfg(feature = "rayon")]
+    pub fn insert_par<I, S>(&mut self, docs: I)
+    where
+        I: IndexedParallelIterator<Item = (D, Vec<S>)>,
+        D: Send,
+        S: AsRef<str> + Send,
+    {
+        let counted: Vec<(D, HashMap<String, u32>)> = docs
+            .map(|(doc, terms)| {
+                let mut counts = HashMap::with_capacity(terms.len());
+                for term in terms {
+                    *counts.entry(term.as_ref().to_string()).or_default() += 1;
+                }
+                (doc, counts)
+            })
+            .collect();
+
+        for (doc, counts) in counted {
+            self.insert_tf_map(doc, &counts);
+        }
+    }
+
+    /// Creates a new doc-vec and inserts it into the indexer without deduplicating `terms`.
+    /// Returns the ID of the new vec. Requires `terms` to be free of duplicates, otherwise the
+    /// document frequencies of repeated terms will be wrong
+    pub fn insert_new_vec_unchecked<S: AsRef<str>>(&mut self, doc: D, terms: &[S]) -> usize {
+        let doc_id = self.vectors.len();
+
         let dimensions = terms
             .iter()
             .map(|term| {
                 let term_id = self.terms.get_or_add_term(term.as_ref());
                 self.terms.update_term_freq(term_id, doc_id as u32);
-                term_id
+                (term_id, 1.0)
             })
-            .collect::<HashSet<_>>();
+            .collect::<Vec<_>>();
 
-        self.terms.update_doc_freq(dimensions.iter().copied());
+        self.terms.update_doc_freq(dimensions.iter().map(|i| i.0));
 
-        // Initialize with same weights for all of them
-        // We'll adjust the weigts later in `finish()`
-        let vec = Vector::create_new_raw(dimensions.into_iter().map(|i| (i, 1.0)).collect());
+        let vec = Vector::create_new_raw(dimensions);
+        self.vectors.push(DocumentVector::new(doc, vec));
+        doc_id
+    }
+
+    /// Creates a new doc-vec and inserts it into the indexer. Returns the ID of the new vec.
+    /// Other than `insert_new_vec` the weights are initialized with the frequency of each term
+    /// within `terms`, which is kept as it is when building with `NoWeight`
+    pub fn insert_raw_tf_vec<S: AsRef<str>>(&mut self, doc: D, terms: &[S]) -> usize {
+        let doc_id = self.vectors.len();
+
+        let dimensions = self.add_doc_terms(doc_id, terms);
+
+        let vec = Vector::create_new_raw(
+            dimensions
+                .into_iter()
+                .map(|i| {
+                    let tf = self.terms.get_term_freq(i, doc_id as u32).unwrap_or(0);
+                    (i, tf as f32)
+                })
+                .collect(),
+        );
         self.vectors.push(DocumentVector::new(doc, vec));
         doc_id
     }
@@ -89,6 +274,29 @@ impl<D> IndexBuilder<D> {
         doc_id
     }
 
+    /// Creates a new doc-vec from a map of terms to their frequency within the document and
+    /// inserts it into the indexer. Returns the ID of the new vec. This is equal to calling
+    /// `insert_new_vec` with each term repeated by its frequency
+    pub fn insert_tf_map(&mut self, doc: D, tf: &HashMap<String, u32>) -> usize {
+        let doc_id = self.vectors.len();
+
+        let dimensions = tf
+            .iter()
+            .filter(|(_, count)| **count > 0)
+            .map(|(term, count)| {
+                let term_id = self.terms.get_or_add_term(term);
+                self.terms.add_term_freq(term_id, doc_id as u32, *count);
+                term_id
+            })
+            .collect::<Vec<_>>();
+
+        self.terms.update_doc_freq(dimensions.iter().copied());
+
+        let vec = Vector::create_new_raw(dimensions.into_iter().map(|i| (i, 1.0)).collect());
+        self.vectors.push(DocumentVector::new(doc, vec));
+        doc_id
+    }
+
     pub fn insert_custom_vec<F>(&mut self, func: F) -> usize
     where
         F: Fn(&mut TermStoreBuilder) -> DocumentVector<D>,
@@ -98,17 +306,54 @@ impl<D> IndexBuilder<D> {
         doc_id
     }
 
+    /// Adds all `terms` of the document with the ID `doc_id` to the term store and updates their
+    /// frequencies. Returns the unique term IDs of the document
+    fn add_doc_terms<S: AsRef<str>>(&mut self, doc_id: usize, terms: &[S]) -> HashSet<u32> {
+        let dimensions = terms
+            .iter()
+            .map(|term| {
+                let term_id = self.terms.get_or_add_term(term.as_ref());
+                self.terms.update_term_freq(term_id, doc_id as u32);
+                term_id
+            })
+            .collect::<HashSet<_>>();
+
+        self.terms.update_doc_freq(dimensions.iter().copied());
+
+        dimensions
+    }
+
     /// Returns the current amount of vectors in the builder
     #[inline]
     pub fn vec_count(&self) -> usize {
         self.vectors.len()
     }
 
+    /// Returns the IDs of all inserted vectors with a length of zero, eg. because all of their
+    /// weights are 0. Those vectors would have a similarity of NaN to every other vector. Note
+    /// that weights applied during `build` are not taken into account, use `with_strict` to check
+    /// the weighted vectors
+    pub fn zero_length_vecs(&self) -> Vec<usize> {
+        self.vectors
+            .iter()
+            .enumerate()
+            .filter(|(_, vec)| vec.vector().get_length() == 0.0)
+            .map(|(id, _)| id)
+            .collect()
+    }
+
     #[inline]
     pub fn vecs_mut(&mut self) -> &mut Vec<DocumentVector<D>> {
         &mut self.vectors
     }
 
+    /// Returns a map from each term to the dimension it gets in the built index. This only stays
+    /// valid as long as no new vectors get inserted
+    #[inline]
+    pub fn dimension_map(&self) -> HashMap<String, u32> {
+        self.terms.dimension_map()
+    }
+
     /// Get a reference to the index builder's vectors.
     #[inline]
     pub fn vectors(&self) -> &[DocumentVector<D>] {
@@ -127,20 +372,94 @@ impl<D: Decodable + Encodable> IndexBuilder<D> {
         Ok(index)
     }
 
+    /// Like `build` but additionally stores the amount of documents and their average length in
+    /// `metadata`. Both are calculated over the documents which end up in the index, counting
+    /// documents collapsed by `with_dedup` as often as they were inserted
+    pub fn build_with_stats<M: Metadata>(self, metadata: M) -> Result<Index<D, M>, Error> {
+        let mut index = self.build(metadata)?;
+
+        let store = &index.vector_store;
+        let doc_count: u64 = (0..store.len() as u32)
+            .map(|id| store.multiplicity(id) as u64)
+            .sum();
+        let total_length: f64 = store
+            .lengths
+            .iter()
+            .enumerate()
+            .map(|(id, len)| *len as f64 * store.multiplicity(id as u32) as f64)
+            .sum();
+        let avg_doc_length = match doc_count {
+            0 => 0.0,
+            n => total_length / n as f64,
+        };
+
+        index.metadata.set_document_count(doc_count as usize);
+        index.metadata.set_avg_doc_length(avg_doc_length as f32);
+        Ok(index)
+    }
+
     pub fn build<M>(mut self, metadata: M) -> Result<Index<D, M>, Error> {
         self.terms.adjust_vecs(&mut self.vectors, &self.term_weight);
 
-        let indexer = TermIndexer::build(self.terms)?;
+        if self.strict {
+            let zero_length = self.zero_length_vecs();
+            if !zero_length.is_empty() {
+                return Err(Error::ZeroLengthVectors(zero_length));
+            }
+        }
 
-        if let Some(filter) = self.output_filter {
-            self.vectors = self
-                .vectors
-                .into_iter()
-                .filter_map(|vec| filter(vec, &indexer))
-                .collect();
+        let lengths = self.terms.doc_lengths(self.vectors.len());
+        let mut term_freqs = vec![];
+        if self.store_term_freqs {
+            term_freqs = self.terms.doc_term_freqs(self.vectors.len());
         }
+        term_freqs.resize(self.vectors.len(), vec![]);
+        // Document frequencies are counted over all inserted documents, including ones getting
+        // filtered out or collapsed
+        let total_documents = self.vectors.len();
+
+        let mut indexer = TermIndexer::build(self.terms, self.store_term_text)?;
 
-        let vstore = vector_store::build(self.vectors)?;
+        let has_external_ids = !self.external_ids.is_empty();
+        let mut external_ids = self.external_ids;
+        external_ids.resize(self.vectors.len(), None);
+
+        let docs = izip!(self.vectors, lengths, external_ids, term_freqs);
+        let docs: Vec<_> = match self.output_filter {
+            Some(filter) => docs
+                .filter_map(|(vec, len, ext, tf)| Some((filter(vec, &indexer)?, len, ext, tf)))
+                .collect(),
+            None => docs.collect(),
+        };
+
+        indexer.set_total_documents(total_documents);
+
+        let (docs, multiplicities) = if self.dedup {
+            dedup_docs(docs, |(vec, ..)| vec.vector())
+        } else {
+            (docs, vec![])
+        };
+
+        let mut vectors = Vec::with_capacity(docs.len());
+        let mut lengths = Vec::with_capacity(docs.len());
+        let mut external_ids = Vec::with_capacity(docs.len());
+        let mut term_freqs = Vec::with_capacity(docs.len());
+        for (vec, len, ext, tf) in docs {
+            vectors.push(vec);
+            lengths.push(len);
+            external_ids.push(ext);
+            term_freqs.push(tf);
+        }
+
+        let mut vstore = vector_store::build(vectors, lengths, self.encoding, self.gap_threshold)?;
+
+        if has_external_ids {
+            vstore.external_ids = external_ids;
+        }
+        vstore.multiplicities = multiplicities;
+        if self.store_term_freqs {
+            vstore.term_freqs = term_freqs;
+        }
 
         Ok(Index {
             metadata,
@@ -150,27 +469,56 @@ impl<D: Decodable + Encodable> IndexBuilder<D> {
     }
 }
 
+/// Collapses all items with identical vectors into the first of them, dropping the others. Returns
+/// the remaining items together with the amount of items each of them represents
+fn dedup_docs<T, F>(items: Vec<T>, vector: F) -> (Vec<T>, Vec<u32>)
+where
+    F: Fn(&T) -> &Vector,
+{
+    let mut seen: HashMap<Vec<(u32, u32)>, usize> = HashMap::with_capacity(items.len());
+    let mut kept = Vec::with_capacity(items.len());
+    let mut multiplicities = Vec::with_capacity(items.len());
+
+    for item in items {
+        let key: Vec<_> = vector(&item)
+            .iter()
+            .map(|(dim, val)| (dim, val.to_bits()))
+            .collect();
+
+        match seen.get(&key) {
+            Some(pos) => multiplicities[*pos] += 1,
+            None => {
+                seen.insert(key, kept.len());
+                kept.push(item);
+                multiplicities.push(1);
+            }
+        }
+    }
+
+    (kept, multiplicities)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    const DOCUMENTS: &[&[&str]] = &[
+        &["to", "drive", "a", "car"],
+        &["to", "have", "a", "call"],
+        &["to", "make", "a", "stand", "a"],
+    ];
+
     #[test]
     fn test_indexer() {
-        let insert_documents: &[&[&str]] = &[
-            &["to", "drive", "a", "car"],
-            &["to", "have", "a", "call"],
-            &["to", "make", "a", "stand", "a"],
-        ];
-
         let mut indexer = IndexBuilder::new();
 
-        for (pos, terms) in insert_documents.iter().enumerate() {
+        for (pos, terms) in DOCUMENTS.iter().enumerate() {
             indexer.insert_new_vec(pos, *terms);
         }
 
         let term_store_builder = &indexer.terms;
 
-        assert!(indexer.vectors.len() == insert_documents.len());
+        assert!(indexer.vectors.len() == DOCUMENTS.len());
         let term_a = term_store_builder.get_term_id("a");
         assert!(term_a.is_some());
         assert_eq!(
@@ -178,6 +526,427 @@ mod test {
             Some(&3)
         );
     }
+
+    #[test]
+    fn test_insert_raw_tf_vec() {
+        let mut builder = IndexBuilder::new().with_weight(weights::NoWeight);
+        builder.insert_raw_tf_vec(0u32, &["a", "b", "a", "c", "a"]);
+        builder.insert_raw_tf_vec(1u32, &["b", "c"]);
+
+        let index = builder.build(()).unwrap();
+        let vec = index.get_vector_store().load_vector(0).unwrap();
+
+        let dim_a = index.get_indexer().get_term("a").unwrap() as u32;
+        let dim_b = index.get_indexer().get_term("b").unwrap() as u32;
+        assert_eq!(vec.vector().get_dim(dim_a), Some(3.0));
+        assert_eq!(vec.vector().get_dim(dim_b), Some(1.0));
+    }
+
+    #[test]
+    fn test_dimension_map() {
+        let mut builder = IndexBuilder::new();
+        for (pos, terms) in DOCUMENTS.iter().enumerate() {
+            builder.insert_new_vec(pos as u32, terms);
+        }
+
+        let dim_map = builder.dimension_map();
+        let index = builder.build(()).unwrap();
+
+        assert_eq!(dim_map.len(), index.get_indexer().len());
+        for (term, dim) in dim_map {
+            assert_eq!(index.get_indexer().get_term(&term), Some(dim as usize));
+        }
+    }
+
+    #[test]
+    fn test_insert_tf_map() {
+        let mut repeated = IndexBuilder::new().with_weight(weights::TFIDF);
+        repeated.insert_new_vec(0u32, &["a", "b", "a", "a", "c"]);
+        repeated.insert_new_vec(1u32, &["b", "d"]);
+
+        let mut tf_map = IndexBuilder::new().with_weight(weights::TFIDF);
+        let tf: HashMap<String, u32> = [("a", 3), ("b", 1), ("c", 1)]
+            .iter()
+            .map(|(term, count)| (term.to_string(), *count))
+            .collect();
+        tf_map.insert_tf_map(0u32, &tf);
+        tf_map.insert_new_vec(1u32, &["b", "d"]);
+
+        let repeated = repeated.build(()).unwrap();
+        let tf_map = tf_map.build(()).unwrap();
+
+        let expected = repeated.get_vector_store().load_vector(0).unwrap();
+        let vec = tf_map.get_vector_store().load_vector(0).unwrap();
+        assert_eq!(vec.vector().sparse_vec(), expected.vector().sparse_vec());
+    }
+
+    #[test]
+    fn test_deterministic_build() {
+        let docs: &[&[(&str, u32)]] = &[
+            &[("to", 1), ("drive", 2), ("a", 1), ("car", 3)],
+            &[("to", 1), ("have", 1), ("a", 2), ("call", 1)],
+            &[("to", 2), ("make", 1), ("a", 1), ("stand", 1)],
+        ];
+
+        // Each build uses new HashMaps with their own random iteration order
+        let build = || {
+            let mut builder = IndexBuilder::new().with_weight(weights::TFIDF);
+            for (pos, terms) in docs.iter().enumerate() {
+                let tf: HashMap<String, u32> = terms
+                    .iter()
+                    .map(|(term, count)| (term.to_string(), *count))
+                    .collect();
+                builder.insert_tf_map(pos as u32, &tf);
+            }
+            builder.build(crate::DefaultMetadata::default()).unwrap()
+        };
+
+        let (a, b) = (build(), build());
+        assert_eq!(
+            bincode::serialize(&a).unwrap(),
+            bincode::serialize(&b).unwrap()
+        );
+
+        let (mut out_a, mut out_b) = (vec![], vec![]);
+        a.write_to(&mut out_a).unwrap();
+        b.write_to(&mut out_b).unwrap();
+        assert_eq!(out_a, out_b);
+    }
+
+    #[test]
+    fn test_zero_length_vecs() {
+        let mut builder = IndexBuilder::new();
+        builder.insert_new_weighted_vec(0u32, &[("a", 1.0), ("b", 0.5)]);
+        builder.insert_new_weighted_vec(1u32, &[("a", 0.0), ("c", 0.0)]);
+        builder.insert_new_vec(2u32, &["b", "c"]);
+
+        assert_eq!(builder.zero_length_vecs(), vec![1]);
+
+        let res = builder.with_strict(true).build(());
+        assert!(matches!(res, Err(Error::ZeroLengthVectors(ids)) if ids == vec![1]));
+    }
+
+    #[test]
+    fn test_doc_lengths() {
+        let mut builder = IndexBuilder::new();
+        for (pos, terms) in DOCUMENTS.iter().enumerate() {
+            builder.insert_new_vec(pos as u32, terms);
+        }
+        builder.with_filter(|vec, _| Some(vec).filter(|i| i.document != 1));
+        let index = builder.build(()).unwrap();
+
+        let store = index.get_vector_store();
+        assert_eq!(store.doc_length(0), Some(4));
+        assert_eq!(store.doc_length(1), Some(5));
+        assert_eq!(store.doc_length(2), None);
+
+        // The filtered document is still counted by the document frequencies
+        assert_eq!(index.get_indexer().total_documents(), DOCUMENTS.len());
+    }
+
+    #[test]
+    fn test_insert_with_id() {
+        let mut builder = IndexBuilder::new();
+        builder.insert_with_id(1_000_000_007, 0u32, &["to", "drive", "a", "car"]);
+        builder.insert_new_vec(1u32, &["to", "have", "a", "call"]);
+        builder.insert_with_id(42, 2u32, &["to", "make", "a", "stand"]);
+        builder.insert_with_id(7, 3u32, &["a", "stand"]);
+        builder.with_filter(|vec, _| Some(vec).filter(|i| i.document != 2));
+        let mut index = builder.build(crate::DefaultMetadata::default()).unwrap();
+
+        assert_eq!(index.external_id(0), Some(1_000_000_007));
+        assert_eq!(index.external_id(1), None);
+        assert_eq!(index.external_id(2), Some(7));
+        assert_eq!(index.external_id(3), None);
+
+        let mut out = vec![];
+        index.write_to(&mut out).unwrap();
+        let read = Index::<u32, crate::DefaultMetadata>::read_from(out.as_slice()).unwrap();
+        for id in 0..4 {
+            assert_eq!(read.external_id(id), index.external_id(id));
+        }
+
+        index.retain_documents(|doc| doc.document != 0).unwrap();
+        assert_eq!(index.external_id(0), None);
+        assert_eq!(index.external_id(1), Some(7));
+
+        let mut builder = IndexBuilder::new();
+        builder.insert_new_vec(0u32, &["a"]);
+        let index = builder.build(()).unwrap();
+        assert!(index.get_vector_store().external_ids.is_empty());
+    }
+
+    #[test]
+    fn test_dedup() {
+        let docs: &[&[&str]] = &[&["a", "b"], &["b", "c"], &["b", "a"], &["c"], &["a", "b"]];
+        let build = |dedup: bool| {
+            let mut builder = IndexBuilder::new().with_dedup(dedup);
+            for (pos, terms) in docs.iter().enumerate() {
+                builder.insert_with_id(100 + pos as u64, pos as u32, terms);
+            }
+            builder.build(crate::DefaultMetadata::default()).unwrap()
+        };
+
+        let index = build(true);
+        let store = index.get_vector_store();
+        assert_eq!(store.len(), 3);
+        let kept: Vec<_> = store.iter().map(|i| i.document).collect();
+        assert_eq!(kept, vec![0, 1, 3]);
+        assert_eq!(store.multiplicity(0), 3);
+        assert_eq!(store.multiplicity(1), 1);
+        assert_eq!(index.get_indexer().total_documents(), docs.len());
+
+        // External IDs of collapsed documents get dropped
+        let external_ids: Vec<_> = (0..store.len()).map(|i| index.external_id(i)).collect();
+        assert_eq!(external_ids, vec![Some(100), Some(101), Some(103)]);
+
+        let mut out = vec![];
+        index.write_to(&mut out).unwrap();
+        let read = Index::<u32, crate::DefaultMetadata>::read_from(out.as_slice()).unwrap();
+        assert_eq!(read.get_vector_store().multiplicity(0), 3);
+
+        let full = build(false);
+        assert_eq!(full.get_vector_store().len(), docs.len());
+        assert_eq!(full.get_vector_store().multiplicity(0), 1);
+    }
+
+    #[test]
+    fn test_build_with_stats() {
+        let mut builder = IndexBuilder::new();
+        for (pos, terms) in DOCUMENTS.iter().enumerate() {
+            builder.insert_new_vec(pos as u32, terms);
+        }
+        let index = builder
+            .build_with_stats(crate::DefaultMetadata::default())
+            .unwrap();
+
+        let metadata = index.get_metadata();
+        assert_eq!(metadata.get_document_count(), 3);
+        assert!((metadata.avg_doc_length() - 13.0 / 3.0).abs() < 0.0001);
+
+        let mut out = vec![];
+        index.write_to(&mut out).unwrap();
+        let read = Index::<u32, crate::DefaultMetadata>::read_from(out.as_slice()).unwrap();
+        assert_eq!(
+            read.get_metadata().avg_doc_length(),
+            metadata.avg_doc_length()
+        );
+
+        // Collapsed duplicates are still counted
+        let mut builder = IndexBuilder::new().with_dedup(true);
+        for (pos, terms) in DOCUMENTS.iter().chain(&DOCUMENTS[2..]).enumerate() {
+            builder.insert_new_vec(pos as u32, terms);
+        }
+        let index = builder
+            .build_with_stats(crate::DefaultMetadata::default())
+            .unwrap();
+        assert_eq!(index.get_vector_store().len(), 3);
+        let metadata = index.get_metadata();
+        assert_eq!(metadata.get_document_count(), 4);
+        assert!((metadata.avg_doc_length() - 18.0 / 4.0).abs() < 0.0001);
+
+        // Older versions don't store the average document length
+        let v3 = crate::DefaultMetadata {
+            avg_doc_length: 2.0,
+            ..crate::DefaultMetadata::new(crate::metadata::IndexVersion::V3)
+        };
+        let enc = v3.encode::<byteorder::LittleEndian>().unwrap();
+        let dec = crate::DefaultMetadata::decode::<byteorder::LittleEndian, _>(enc.as_slice());
+        assert_eq!(dec.unwrap().avg_doc_length(), 0.0);
+    }
+
+    #[test]
+    fn test_quantization() {
+        let docs: &[&[&str]] = &[&["a", "b", "b"], &["b", "c"], &["a", "c", "c", "c"], &["d"]];
+        let build = |quantize: bool| {
+            let mut builder = IndexBuilder::new().with_quantization(quantize);
+            for (pos, terms) in docs.iter().enumerate() {
+                builder.insert_new_vec(pos as u32, terms);
+            }
+            builder.build(()).unwrap()
+        };
+
+        let exact = build(false);
+        let quantized = build(true);
+        assert!(!exact.get_vector_store().is_quantized());
+        assert!(quantized.get_vector_store().is_quantized());
+
+        let query = exact.build_vector(&["a", "c"], None).unwrap();
+        let exact_res = exact.knn(&query, 4);
+        let quantized_res = quantized.knn(&query, 4);
+        assert_eq!(exact_res.len(), quantized_res.len());
+        for (e, q) in exact_res.iter().zip(quantized_res.iter()) {
+            assert_eq!(e.0, q.0);
+            assert!((e.1 - q.1).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_insert_new_vec_unchecked() {
+        let docs: &[&[&str]] = &[&["a", "b", "c"], &["c", "d"], &["e", "a"]];
+
+        let mut checked = IndexBuilder::new();
+        let mut unchecked = IndexBuilder::new();
+        for (pos, terms) in docs.iter().enumerate() {
+            checked.insert_new_vec(pos as u32, terms);
+            unchecked.insert_new_vec_unchecked(pos as u32, terms);
+        }
+        let checked = checked.build(()).unwrap();
+        let unchecked = unchecked.build(()).unwrap();
+
+        assert!(checked.diff(&unchecked).is_empty());
+        let vectors = |index: &Index<u32, ()>| -> Vec<_> {
+            index
+                .get_vector_store()
+                .iter()
+                .map(|i| i.vector().clone())
+                .collect()
+        };
+        assert_eq!(vectors(&checked), vectors(&unchecked));
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_from_jsonl() {
+        let data = r#"{"id": 4, "text": "to drive a car"}
+
+{"id": 7, "text": "to have a call"}
+"#;
+        let builder = IndexBuilder::from_jsonl(data.as_bytes(), |value| {
+            let id = value["id"].as_u64().unwrap() as u32;
+            let terms = value["text"]
+                .as_str()
+                .unwrap()
+                .split(' ')
+                .map(|i| i.to_string())
+                .collect();
+            (id, terms)
+        })
+        .unwrap();
+        let index = builder.build(()).unwrap();
+
+        let docs: Vec<_> = index
+            .get_vector_store()
+            .iter()
+            .map(|i| i.document)
+            .collect();
+        assert_eq!(docs, vec![4, 7]);
+        assert_eq!(index.get_indexer().len(), 6);
+
+        let invalid = IndexBuilder::<u32>::from_jsonl("{".as_bytes(), |_| (0, vec![]));
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_columnar_encoding() {
+        let build = |encoding: VectorEncoding| {
+            let mut builder = IndexBuilder::new().with_encoding(encoding);
+            builder.insert_new_vec(0u32, &["a", "b", "b"]);
+            builder.insert_new_vec(1u32, &["b", "c"]);
+            builder.insert_new_vec(2u32, &["a", "c", "c"]);
+            builder.build(crate::DefaultMetadata::default()).unwrap()
+        };
+
+        let packed = build(VectorEncoding::Packed);
+        let columnar = build(VectorEncoding::Columnar);
+        assert_eq!(
+            columnar.get_vector_store().encoding(),
+            VectorEncoding::Columnar
+        );
+        assert!(packed.diff(&columnar).is_empty());
+
+        let mut out = vec![];
+        columnar.write_to(&mut out).unwrap();
+        let read = Index::<u32, crate::DefaultMetadata>::read_from(out.as_slice()).unwrap();
+        assert_eq!(read.get_vector_store().encoding(), VectorEncoding::Columnar);
+        assert!(packed.diff(&read).is_empty());
+
+        let query = packed.build_vector(&["a", "c"], None).unwrap();
+        let ids = 0..packed.get_vector_store().len() as u32;
+        let expected: Vec<_> = packed
+            .get_vector_store()
+            .score_all(&query, ids.clone())
+            .collect();
+        let scored: Vec<_> = read.get_vector_store().score_all(&query, ids).collect();
+        assert_eq!(scored, expected);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_insert_par() {
+        use rayon::iter::IntoParallelIterator;
+
+        let docs: Vec<(u32, Vec<String>)> = (0..200u32)
+            .map(|i| {
+                let terms = (0..(i % 7 + 1))
+                    .map(|j| format!("t{}", (i * 31 + j * 17) % 53))
+                    .collect();
+                (i, terms)
+            })
+            .collect();
+
+        let mut serial = IndexBuilder::new();
+        for (doc, terms) in docs.iter() {
+            serial.insert_new_vec(*doc, terms);
+        }
+        let mut parallel = IndexBuilder::new();
+        parallel.insert_par(docs.clone().into_par_iter());
+
+        let serial = serial.build(()).unwrap();
+        let parallel = parallel.build(()).unwrap();
+        assert!(serial.diff(&parallel).is_empty());
+        for (a, b) in serial
+            .get_vector_store()
+            .iter()
+            .zip(parallel.get_vector_store().iter())
+        {
+            assert_eq!(
+                a.vector().iter().collect::<Vec<_>>(),
+                b.vector().iter().collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_without_term_text() {
+        let mut builder = IndexBuilder::new().with_term_text(false);
+        builder.insert_new_vec(0u32, &["a", "b"]);
+        builder.insert_new_vec(1u32, &["b", "c"]);
+        let dim_b = builder.dimension_map()["b"];
+        let index = builder.build(crate::DefaultMetadata::default()).unwrap();
+
+        let term = index.get_indexer().load_term(dim_b as usize).unwrap();
+        assert_eq!(term.text(), "");
+        assert_eq!(term.doc_frequency(), 2);
+        assert_eq!(index.get_indexer().get_term("b"), None);
+
+        // The empty texts must not be found either
+        assert!(!index.get_indexer().has_text());
+        assert_eq!(index.get_indexer().get_term(""), None);
+        assert!(index.build_vector(&[""], None).is_none());
+        assert_eq!(index.term_for_dim(dim_b as usize), None);
+        #[cfg(feature = "genbktree")]
+        {
+            assert!(index.suggest("", 1).is_empty());
+            assert!(index.build_vector_fuzzy(&["", "bb"], 1, 0.5).is_none());
+        }
+
+        let mut out = vec![];
+        index.write_to(&mut out).unwrap();
+        let read = Index::<u32, crate::DefaultMetadata>::read_from(out.as_slice()).unwrap();
+        assert!(!read.get_indexer().has_text());
+        assert_eq!(read.get_indexer().get_term(""), None);
+
+        let mut docs: Vec<_> = index
+            .get_vector_store()
+            .get_in_dim(dim_b)
+            .unwrap()
+            .into_iter()
+            .map(|i| i.document)
+            .collect();
+        docs.sort_unstable();
+        assert_eq!(docs, vec![0, 1]);
+    }
 }
 
 impl<D> Default for IndexBuilder<D> {
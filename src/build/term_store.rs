@@ -59,7 +59,13 @@ impl TermStoreBuilder {
 
     #[inline]
     pub fn update_term_freq(&mut self, term_id: u32, doc_id: u32) {
-        *self.term_freq.entry((term_id, doc_id)).or_default() += 1;
+        self.add_term_freq(term_id, doc_id, 1);
+    }
+
+    /// Increases the frequency of a term within a document by `count`
+    #[inline]
+    pub fn add_term_freq(&mut self, term_id: u32, doc_id: u32, count: u32) {
+        *self.term_freq.entry((term_id, doc_id)).or_default() += count;
     }
 
     #[inline]
@@ -79,6 +85,38 @@ impl TermStoreBuilder {
         &self.doc_freq
     }
 
+    /// Returns the amount of terms, including repeated ones, of the first `doc_count` documents
+    pub fn doc_lengths(&self, doc_count: usize) -> Vec<u32> {
+        let mut lengths = vec![0; doc_count];
+
+        for ((_, doc_id), freq) in self.term_freq.iter() {
+            if let Some(len) = lengths.get_mut(*doc_id as usize) {
+                *len += *freq;
+            }
+        }
+
+        lengths
+    }
+
+    /// Returns the frequency of each term within each of the first `doc_count` documents as
+    /// (dimension, frequency) pairs sorted by the dimension. Requires `adjust_vecs` to be called
+    /// first
+    pub fn doc_term_freqs(&self, doc_count: usize) -> Vec<Vec<(u32, u32)>> {
+        let mut term_freqs = vec![vec![]; doc_count];
+
+        for ((term_id, doc_id), freq) in self.term_freq.iter() {
+            if let Some(doc) = term_freqs.get_mut(*doc_id as usize) {
+                doc.push((self.get_sorted_term_pos(*term_id), *freq));
+            }
+        }
+
+        for doc in term_freqs.iter_mut() {
+            doc.sort_unstable();
+        }
+
+        term_freqs
+    }
+
     /// Get a reference to the term store builder's terms.
     pub fn terms(&self) -> &HashMap<String, u32> {
         &self.terms
@@ -122,15 +160,31 @@ impl TermStoreBuilder {
         }
     }
 
+    /// Returns a map from each term to the dimension it gets in the built index. This only stays
+    /// valid as long as no new terms get added
+    pub fn dimension_map(&self) -> HashMap<String, u32> {
+        self.sorted_terms()
+            .into_iter()
+            .enumerate()
+            .map(|(pos, (term, _))| (term.to_string(), pos as u32))
+            .collect()
+    }
+
     /// Builds the a map of ID to ordered position of the term if the terms were sorted
     fn build_order_map(&mut self) {
         self.order_map.reserve(self.terms.len());
 
-        let mut term_vec: Vec<_> = self.terms.iter().map(|(term, id)| (term, *id)).collect();
-        term_vec.sort_by(|a, b| a.0.cmp(b.0));
+        let ids: Vec<_> = self.sorted_terms().into_iter().map(|i| i.1).collect();
 
-        for (pos, (_, id)) in term_vec.into_iter().enumerate() {
+        for (pos, id) in ids.into_iter().enumerate() {
             self.order_map.insert(id, pos as u32);
         }
     }
+
+    /// Returns all terms with their IDs, sorted by the terms
+    fn sorted_terms(&self) -> Vec<(&String, u32)> {
+        let mut term_vec: Vec<_> = self.terms.iter().map(|(term, id)| (term, *id)).collect();
+        term_vec.sort_by(|a, b| a.0.cmp(b.0));
+        term_vec
+    }
 }
@@ -25,6 +25,17 @@ impl TermWeight for TFIDF {
     }
 }
 
+/// TF.IDF with a smoothed IDF. Terms occurring in all documents keep a small positive weight
+/// instead of getting a weight of 0
+pub struct TFIDFSmooth;
+impl TermWeight for TFIDFSmooth {
+    #[inline]
+    fn weight(&self, _current: f32, tf: usize, df: usize, total_docs: usize) -> f32 {
+        let idf = ((total_docs as f32 + 1.0) / (df as f32 + 1.0)).log10() + 1.0;
+        ((tf as f32).log10() + 1.0) * idf
+    }
+}
+
 /// Normalized term frequency
 pub struct NormalizedTF;
 impl TermWeight for NormalizedTF {
@@ -41,3 +52,18 @@ impl TermWeight for NoWeight {
         current
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tfidf_smooth() {
+        // Term in all documents
+        assert_eq!(DefaultTFIDF.weight(1.0, 1, 10, 10), 0.0);
+        assert!(TFIDFSmooth.weight(1.0, 1, 10, 10) > 0.0);
+
+        // Rare terms still have a higher weight
+        assert!(TFIDFSmooth.weight(1.0, 1, 1, 10) > TFIDFSmooth.weight(1.0, 1, 10, 10));
+    }
+}
@@ -3,12 +3,8 @@ use crate::{
     traits::{Decodable, Encodable},
     vector::Vector,
 };
-use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt};
-use std::{
-    cmp::Ordering,
-    hash::Hash,
-    io::{Read, Write},
-};
+use byteorder::ByteOrder;
+use std::{cmp::Ordering, hash::Hash, io::Read};
 
 /// A structure representing a document with its calculated document-vector
 #[derive(Clone, Debug, Eq)]
@@ -29,6 +25,35 @@ impl<D> DocumentVector<D> {
         self.vec.similarity(&other.vec)
     }
 
+    /// Calculates the similarity to `other` using a custom similarity function `f`
+    #[inline]
+    pub fn similarity_with<O, F>(&self, other: &DocumentVector<O>, f: F) -> f32
+    where
+        F: Fn(&Vector, &Vector) -> f32,
+    {
+        f(&self.vec, &other.vec)
+    }
+
+    /// Calculates the similarity to the query vector `q` using its precomputed inverse length
+    /// `q_inv_len` (`1.0 / q.get_length()`). This avoids recalculating the queries length when
+    /// scoring many documents against the same query.
+    #[inline]
+    pub fn score_against(&self, q: &Vector, q_inv_len: f32) -> f32 {
+        let sc = self.vec.scalar(q);
+        if sc == 0.0 {
+            return 0.0;
+        }
+        sc * (1.0 / self.vec.get_length()) * q_inv_len
+    }
+
+    /// Returns the amount of distinct terms in the document. Repeated terms are only counted once
+    /// since only the vector is stored. Getting the real amount of terms including repetitions
+    /// would require storing the documents length separately while building the index
+    #[inline]
+    pub fn term_count(&self) -> usize {
+        self.vec.dimen_count()
+    }
+
     #[inline(always)]
     pub fn vector(&self) -> &Vector {
         &self.vec
@@ -78,25 +103,8 @@ impl<D: PartialEq> PartialEq for DocumentVector<D> {
 
 impl<D: Encodable> Encodable for DocumentVector<D> {
     fn encode<T: ByteOrder>(&self) -> Result<Vec<u8>, Error> {
-        let doc_enc = self.document.encode::<T>()?;
-        let svec = self.vec.sparse_vec();
-
-        let mut encoded = Vec::with_capacity(6 + (svec.len() * 6) + doc_enc.len());
-
-        // 0..4 vector length
-        encoded.write_f32::<T>(self.vec.get_length())?;
-
-        // 4..6 vector-dimension count
-        encoded.write_u16::<T>(svec.len() as u16)?;
-
-        // n*u48..
-        for (dimension, value) in svec {
-            encoded.write_u24::<T>(*dimension)?;
-            encoded.write_f32::<T>(*value)?;
-        }
-
-        encoded.write_all(&doc_enc)?;
-
+        let mut encoded = self.vec.encode::<T>()?;
+        encoded.extend(self.document.encode::<T>()?);
         Ok(encoded)
     }
 }
@@ -104,24 +112,134 @@ impl<D: Encodable> Encodable for DocumentVector<D> {
 impl<D: Decodable> Decodable for DocumentVector<D> {
     #[inline]
     fn decode<T: ByteOrder, R: Read>(mut data: R) -> Result<Self, Error> {
-        // 0..4 vector length
-        let vec_length = data.read_f32::<T>()?;
+        let vec = Vector::decode::<T, _>(&mut data)?;
+        let doc = D::decode::<T, _>(data)?;
+        Ok(DocumentVector::new(doc, vec))
+    }
+}
 
-        // 4..6 vector-dimension count
-        let vector_dim_count = data.read_u16::<T>()?;
+impl<D: Encodable> DocumentVector<D> {
+    /// Encodes the document using `Vector::encode_quantized` for its vector. Values are stored
+    /// with 256 steps relative to the vectors highest value, so similarities computed on decoded
+    /// vectors are only approximations. Rankings are mostly preserved but documents with very
+    /// close scores might swap places
+    pub fn encode_quantized<T: ByteOrder>(&self) -> Result<Vec<u8>, Error> {
+        let mut encoded = self.vec.encode_quantized::<T>()?;
+        encoded.extend(self.document.encode::<T>()?);
+        Ok(encoded)
+    }
 
-        let dimensions: Vec<_> = (0..vector_dim_count)
-            .map(|_| -> Result<_, std::io::Error> {
-                let dim = data.read_u24::<T>()?;
-                let val = data.read_f32::<T>()?;
-                Ok((dim, val))
-            })
-            .collect::<Result<_, _>>()?;
+    /// Encodes the document using `Vector::encode_columnar` for its vector
+    pub fn encode_columnar<T: ByteOrder>(&self) -> Result<Vec<u8>, Error> {
+        let mut encoded = self.vec.encode_columnar::<T>()?;
+        encoded.extend(self.document.encode::<T>()?);
+        Ok(encoded)
+    }
+}
 
+impl<D: Decodable> DocumentVector<D> {
+    /// Decodes a document encoded with `encode_columnar`
+    #[inline]
+    pub fn decode_columnar<T: ByteOrder, R: Read>(mut data: R) -> Result<Self, Error> {
+        let vec = Vector::decode_columnar::<T, _>(&mut data)?;
         let doc = D::decode::<T, _>(data)?;
+        Ok(DocumentVector::new(doc, vec))
+    }
 
-        let vec = Vector::new_raw(dimensions, vec_length);
-
+    /// Decodes a document encoded with `encode_quantized`
+    #[inline]
+    pub fn decode_quantized<T: ByteOrder, R: Read>(mut data: R) -> Result<Self, Error> {
+        let vec = Vector::decode_quantized::<T, _>(&mut data)?;
+        let doc = D::decode::<T, _>(data)?;
         Ok(DocumentVector::new(doc, vec))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::build::IndexBuilder;
+    use byteorder::LittleEndian;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_score_against() {
+        let doc = DocumentVector::new(0u32, Vector::create_new_raw(vec![(1, 0.5), (4, 2.0)]));
+        let query = Vector::create_new_raw(vec![(1, 1.0), (2, 3.0), (4, 0.25)]);
+        let q_inv_len = 1.0 / query.get_length();
+
+        let expected = doc.vector().similarity(&query);
+        assert!((doc.score_against(&query, q_inv_len) - expected).abs() < 1e-6);
+
+        let other = Vector::create_new_raw(vec![(7, 1.0)]);
+        assert_eq!(doc.score_against(&other, 1.0 / other.get_length()), 0.0);
+    }
+
+    #[test]
+    fn test_term_count() {
+        let documents: &[&[&str]] = &[
+            &["to", "drive", "a", "car"],
+            &["to", "make", "a", "stand", "a"],
+        ];
+
+        let mut builder = IndexBuilder::new();
+        for (pos, terms) in documents.iter().enumerate() {
+            builder.insert_new_vec(pos as u32, terms);
+        }
+        let index = builder.build(()).unwrap();
+
+        for (pos, terms) in documents.iter().enumerate() {
+            let doc = index.get_vector_store().load_vector(pos).unwrap();
+            let unique = terms.iter().collect::<HashSet<_>>().len();
+            assert_eq!(doc.term_count(), unique);
+        }
+    }
+
+    #[test]
+    fn test_similarity_with() {
+        let a = DocumentVector::new(0u32, Vector::create_new_raw(vec![(1, 1.0), (2, 1.0)]));
+        let b = DocumentVector::new(1u32, Vector::create_new_raw(vec![(2, 1.0), (3, 1.0)]));
+
+        let overlap = a.similarity_with(&b, |a, b| a.overlap_count(b) as f32);
+        assert_eq!(overlap, 1.0);
+
+        let cosine = a.similarity_with(&b, Vector::similarity);
+        assert_eq!(cosine, a.similarity(&b));
+    }
+
+    #[test]
+    fn test_quantized_ranking() {
+        let query = Vector::create_new_raw(vec![(0, 1.0), (1, 0.5), (2, 0.25)]);
+        let docs: Vec<_> = (0..20u32)
+            .map(|i| {
+                let i = i as f32;
+                let vec = vec![(0, 0.1 + i * 0.3), (1, 5.0 - i * 0.2), (2, 1.0), (3, i)];
+                DocumentVector::new(i as u32, Vector::create_new_raw(vec))
+            })
+            .collect();
+
+        let quantized: Vec<DocumentVector<u32>> = docs
+            .iter()
+            .map(|doc| {
+                let enc = doc.encode_quantized::<LittleEndian>().unwrap();
+                DocumentVector::decode_quantized::<LittleEndian, _>(&enc[..]).unwrap()
+            })
+            .collect();
+
+        let ranking = |docs: &[DocumentVector<u32>]| {
+            let mut scores: Vec<_> = docs
+                .iter()
+                .map(|i| (i.document, i.vector().similarity(&query)))
+                .collect();
+            scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+            scores
+        };
+
+        let exact = ranking(&docs);
+        let approx = ranking(&quantized);
+        for (e, a) in exact.iter().zip(approx.iter()) {
+            assert_eq!(e.0, a.0);
+            assert!((e.1 - a.1).abs() < 0.01);
+        }
+    }
+}
@@ -5,9 +5,24 @@ pub enum Error {
     Io(std::io::Error),
     UTF8Error,
     Decode,
+    NamespaceNotFound(String),
+    DuplicateNamespace(String),
     IndexedFile(indexed_file::error::Error),
     InvalidIndex,
     Bincode(bincode::Error),
+    NonFinite,
+    ZeroLengthVectors(Vec<usize>),
+    MissingTermFreqs,
+    #[cfg(feature = "serde_json")]
+    Json(serde_json::Error),
+}
+
+#[cfg(feature = "serde_json")]
+impl From<serde_json::Error> for Error {
+    #[inline]
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
 }
 
 impl From<bincode::Error> for Error {
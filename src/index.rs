@@ -1,16 +1,54 @@
+#[cfg(any(feature = "genbktree", feature = "serde_json"))]
+use crate::term_store::item::IndexTerm;
 use crate::{
-    build::weights::TermWeight, error::Error, metadata::Metadata, term_store::TermIndexer,
-    traits::Decodable, vector_store::VectorStore, Vector,
+    build::weights::TermWeight,
+    error::Error,
+    inv_index::InvertedIndex,
+    lock_step::LockStepOuterIter,
+    metadata::{IndexVersion, Metadata},
+    term_store::{LegacyTermIndexer, TermIndexer},
+    traits::{Decodable, Encodable},
+    vector::Conflict,
+    vector_store::{self, LegacyVectorStore, VectorEncoding, VectorStore},
+    DocumentVector, Vector,
 };
+use bincode::Options;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use indexed_file::mem_file::MemFile;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap, HashSet},
+    convert::TryFrom,
     fs::File,
-    io::{BufReader, Read},
+    io::{BufReader, ErrorKind, Read, Write},
     path::Path,
 };
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// Magic bytes at the beginning of an index written with `Index::write_to`
+const FORMAT_MAGIC: &[u8; 4] = b"VSMI";
+/// Version of the format written by `Index::write_to`. Since version 2 terms are front coded
+const FORMAT_VERSION: u8 = 2;
+
+// Section IDs of the format written by `Index::write_to`
+const SECTION_METADATA: u8 = 0;
+const SECTION_TERMS: u8 = 1;
+const SECTION_INV_INDEX: u8 = 2;
+const SECTION_VECTORS: u8 = 3;
+const SECTION_DOC_LENGTHS: u8 = 4;
+const SECTION_VEC_ENCODING: u8 = 5;
+const SECTION_EXTERNAL_IDS: u8 = 6;
+const SECTION_MULTIPLICITIES: u8 = 7;
+const SECTION_TERM_FREQS: u8 = 8;
+/// Empty section which is only written if the index doesn't store the texts of its terms
+const SECTION_NO_TERM_TEXT: u8 = 9;
+
+/// An index of document vectors. All lookups only need shared access, so an `Index` is `Send`
+/// and `Sync` as long as `D` and `M` are, allowing it to be queried from multiple threads at once.
+/// Wrap it into an `Arc` to share it cheaply, eg. between the tasks of a server, and use
+/// `clone_heavy` to get a copy which can be modified
 #[derive(Serialize, Deserialize)]
 pub struct Index<D: Decodable, M> {
     pub(crate) metadata: M,
@@ -39,6 +77,34 @@ impl<D: Decodable, M> Index<D, M> {
         &self.metadata
     }
 
+    /// Returns the external ID of the document with the ID `internal`, assigned using
+    /// `IndexBuilder::insert_with_id`
+    #[inline]
+    pub fn external_id(&self, internal: usize) -> Option<u64> {
+        self.vector_store
+            .external_ids
+            .get(internal)
+            .copied()
+            .flatten()
+    }
+
+    /// Returns the term of the given dimension. Returns `None` if the index was built without the
+    /// texts of the terms
+    #[inline]
+    pub fn term_for_dim(&self, dim: usize) -> Option<String> {
+        if !self.indexer.has_text() {
+            return None;
+        }
+
+        self.indexer.load_term(dim).map(|t| t.text().to_string())
+    }
+
+    /// Returns the dimension of the given term
+    #[inline]
+    pub fn dim_for_term(&self, term: &str) -> Option<usize> {
+        self.indexer.get_term(term)
+    }
+
     #[inline]
     pub fn is_empty(&self) -> bool {
         self.indexer.is_empty() || self.vector_store.is_empty()
@@ -60,6 +126,25 @@ impl<D: Decodable, M> Index<D, M> {
         Some(Vector::create_new_raw(terms))
     }
 
+    /// Builds a vector from `terms` weighting each term by its inverse document frequency
+    /// multiplied with the terms boost. This allows giving some terms, eg. from a title, a higher
+    /// importance. Returns `None` if no term could be found
+    pub fn build_vector_boosted(&self, terms: &[(&str, f32)]) -> Option<Vector> {
+        let terms: Vec<_> = terms
+            .iter()
+            .filter_map(|(term, boost)| {
+                let dim = self.indexer.get_term(term)? as u32;
+                Some((dim, self.idf(dim) * boost))
+            })
+            .collect();
+
+        if terms.is_empty() {
+            return None;
+        }
+
+        Some(Vector::create_new_raw(terms))
+    }
+
     pub fn build_vector<S: AsRef<str>>(
         &self,
         terms: &[S],
@@ -76,7 +161,7 @@ impl<D: Decodable, M> Index<D, M> {
                 let mut res_weight = 1.0;
                 if let Some(w) = weight.as_ref() {
                     res_weight =
-                        w.weight(1.0, 1, i.doc_frequency() as usize, self.vector_store.len());
+                        w.weight(1.0, 1, i.doc_frequency() as usize, self.total_documents());
                 }
                 (pos as u32, res_weight)
             })
@@ -89,6 +174,402 @@ impl<D: Decodable, M> Index<D, M> {
         Some(Vector::create_new_raw(terms))
     }
 
+    /// Like `build_vector` without a weight but additionally returns all terms which couldn't be
+    /// found in the index. This helps finding out why a query doesn't have any result
+    pub fn build_vector_reporting(&self, terms: &[&str]) -> (Option<Vector>, Vec<String>) {
+        let unresolved = terms
+            .iter()
+            .filter(|term| self.indexer.get_term(term).is_none())
+            .map(|term| term.to_string())
+            .collect();
+
+        (self.build_vector(terms, None), unresolved)
+    }
+
+    /// Builds a vector from `terms` using the amount of times each term occurs in `terms` as
+    /// weight, without taking the document frequency into account. Returns `None` if no term
+    /// could be found
+    pub fn build_tf_vector<S: AsRef<str>>(&self, terms: &[S]) -> Option<Vector> {
+        let mut counts: HashMap<u32, f32> = HashMap::with_capacity(terms.len());
+
+        for term in terms {
+            if let Some(dim) = self.indexer.get_term(term.as_ref()) {
+                *counts.entry(dim as u32).or_default() += 1.0;
+            }
+        }
+
+        if counts.is_empty() {
+            return None;
+        }
+
+        Some(Vector::create_new_raw(counts.into_iter().collect()))
+    }
+
+    /// Builds a vector from `terms` leaving out all terms which occur in more than `max_df_ratio`
+    /// of all documents. Very common terms match most documents without helping to rank them, so
+    /// pruning them keeps the amount of candidates small. Returns `None` if no term is left
+    pub fn build_vector_pruned(&self, terms: &[&str], max_df_ratio: f32) -> Option<Vector> {
+        let total_docs = self.total_documents() as f32;
+
+        let dims: Vec<_> = terms
+            .iter()
+            .filter_map(|term| {
+                let dim = self.indexer.get_term(term)?;
+                let item = self.indexer.load_term(dim)?;
+                let ratio = item.doc_frequency() as f32 / total_docs;
+                if ratio > max_df_ratio {
+                    return None;
+                }
+                Some((dim as u32, 1.0))
+            })
+            .collect();
+
+        if dims.is_empty() {
+            return None;
+        }
+
+        Some(Vector::create_new_raw(dims))
+    }
+
+    /// Returns spelling suggestions for `term` with an edit distance of at most `max_dist`.
+    /// Suggestions are ordered by their distance and terms occurring in more documents first
+    #[cfg(feature = "genbktree")]
+    pub fn suggest(&self, term: &str, max_dist: usize) -> Vec<String> {
+        self.similar_terms(term, max_dist)
+            .into_iter()
+            .map(|(term, _)| term.text().to_string())
+            .collect()
+    }
+
+    /// Builds a vector from `terms` where each term that can't be found is replaced by its closest
+    /// term with an edit distance of at most `max_dist`. Exact matches get a weight of `1.0` while
+    /// replaced terms get weighted with `fuzzy_penalty`. If multiple terms end up in the same
+    /// dimension, the biggest weight is used. Returns `None` if no term could be found
+    #[cfg(feature = "genbktree")]
+    pub fn build_vector_fuzzy(
+        &self,
+        terms: &[&str],
+        max_dist: usize,
+        fuzzy_penalty: f32,
+    ) -> Option<Vector> {
+        let dims = terms.iter().filter_map(|term| {
+            if let Some(dim) = self.indexer.get_term(term) {
+                return Some((dim as u32, 1.0));
+            }
+
+            let (closest, _) = self.similar_terms(term, max_dist).into_iter().next()?;
+            let dim = self.indexer.get_term(closest.text())?;
+            Some((dim as u32, fuzzy_penalty))
+        });
+
+        let mut vec = Vector::new_empty();
+        for (dim, weight) in dims {
+            vec.push_dim(dim, weight, Conflict::Max);
+        }
+
+        if vec.is_empty() {
+            return None;
+        }
+
+        vec.update();
+        Some(vec)
+    }
+
+    /// Returns all terms with an edit distance of at most `max_dist` to `term` ordered by their
+    /// distance and terms occurring in more documents first
+    #[cfg(feature = "genbktree")]
+    fn similar_terms(&self, term: &str, max_dist: usize) -> Vec<(IndexTerm, usize)> {
+        let mut similar = self.indexer.find_similar(term, max_dist);
+
+        similar.sort_by(|a, b| {
+            a.1.cmp(&b.1)
+                .then_with(|| b.0.doc_frequency().cmp(&a.0.doc_frequency()))
+                .then_with(|| a.0.text().cmp(b.0.text()))
+        });
+
+        similar
+    }
+
+    /// Returns the IDs of the `k` documents most similar to `query` together with their similarity
+    /// in descending order
+    pub fn knn(&self, query: &Vector, k: usize) -> Vec<(u32, f32)> {
+        if k == 0 || !self.can_search(query) {
+            return vec![];
+        }
+
+        if query.dimen_count() == 1 {
+            return top_k(self.score_single_dim(query), k);
+        }
+
+        top_k(self.score_candidates(query), k)
+    }
+
+    /// Scores all documents containing the only dimension of `query`. The similarity reduces to
+    /// the documents value of this dimension divided by the documents length, times the sign of
+    /// the queries value
+    fn score_single_dim<'a>(&'a self, query: &'a Vector) -> impl Iterator<Item = (u32, f32)> + 'a {
+        let (dim, q_val) = query.iter().next().unwrap_or((0, 0.0));
+        let q_factor = q_val / query.get_length();
+
+        let postings = self.vector_store.get_map().get(dim).unwrap_or_default();
+        postings.into_iter().filter_map(move |id| {
+            let doc = self.vector_store.load_vector(id as usize)?;
+            let d_val = doc.vector().get_dim(dim)?;
+            let doc_len = doc.vector().get_length();
+            if d_val == 0.0 || doc_len == 0.0 {
+                return Some((id, 0.0));
+            }
+            Some((id, d_val / doc_len * q_factor))
+        })
+    }
+
+    /// Returns the IDs of all documents with a similarity to `query` of at least `min_sim`
+    /// together with their similarity in descending order
+    pub fn search_above(&self, query: &Vector, min_sim: f32) -> Vec<(u32, f32)> {
+        if !self.can_search(query) {
+            return vec![];
+        }
+
+        let mut res: Vec<_> = self
+            .score_candidates(query)
+            .filter(|(_, sim)| *sim >= min_sim)
+            .collect();
+        sort_scored(&mut res);
+        res
+    }
+
+    /// Returns the IDs of the `k` documents most similar to the document with the ID `doc_id`
+    /// together with their similarity in descending order. The document itself is not included
+    pub fn more_like(&self, doc_id: usize, k: usize) -> Vec<(u32, f32)> {
+        let doc = match self.vector_store.load_vector(doc_id) {
+            Some(doc) => doc,
+            None => return vec![],
+        };
+
+        let mut res = self.knn(doc.vector(), k + 1);
+        res.retain(|(id, _)| *id as usize != doc_id);
+        res.truncate(k);
+        res
+    }
+
+    /// Returns the IDs of at most `k` documents with a similarity to `query` of at least `min_sim`
+    /// together with their similarity in descending order
+    pub fn search(&self, query: &Vector, k: usize, min_sim: f32) -> Vec<(u32, f32)> {
+        if k == 0 || !self.can_search(query) {
+            return vec![];
+        }
+
+        let candidates = self
+            .score_candidates(query)
+            .filter(|(_, sim)| *sim >= min_sim);
+        top_k(candidates, k)
+    }
+
+    /// Returns the IDs of the `k` documents with the highest `Vector::overlap_ratio` to `query`
+    /// together with their ratio in descending order. This ranks documents by how many of the
+    /// query terms they contain, regardless of their size
+    pub fn knn_overlap(&self, query: &Vector, k: usize) -> Vec<(u32, f32)> {
+        if k == 0 || !self.can_search(query) {
+            return vec![];
+        }
+
+        let candidates = self.vector_store.get_in_dims_iter(query.vec_indices());
+        let scored = candidates.into_iter().filter_map(|id| {
+            let doc = self.vector_store.load_vector(id as usize)?;
+            Some((id, query.overlap_ratio(doc.vector())))
+        });
+        top_k(scored, k)
+    }
+
+    /// Like `knn` but additionally returns the amount of dimensions each document shares with
+    /// `query`. Both get calculated in a single pass over the vectors, which allows re-ranking
+    /// documents with equal similarity by their coverage of the query
+    pub fn knn_with_coverage(&self, query: &Vector, k: usize) -> Vec<(u32, f32, usize)> {
+        if k == 0 || !self.can_search(query) {
+            return vec![];
+        }
+
+        let candidates = self.vector_store.get_in_dims_iter(query.vec_indices());
+        let scored = candidates.into_iter().filter_map(|id| {
+            let doc = self.vector_store.load_vector(id as usize)?;
+
+            let mut scalar = 0.0;
+            let mut coverage = 0;
+            for (_, q_val, d_val) in query.overlapping(doc.vector()) {
+                scalar += q_val * d_val;
+                coverage += 1;
+            }
+
+            let length = query.get_length() * doc.vector().get_length();
+            let sim = if scalar == 0.0 { 0.0 } else { scalar / length };
+            Some((id, sim, coverage))
+        });
+
+        top_k_with(scored, k)
+    }
+
+    /// Like `knn` but only uses the dimensions of `query` which are in `allow` and not in `deny`.
+    /// Passing `None` for a mask disables it
+    pub fn search_masked(
+        &self,
+        query: &Vector,
+        allow: Option<&HashSet<u32>>,
+        deny: Option<&HashSet<u32>>,
+        k: usize,
+    ) -> Vec<(u32, f32)> {
+        let dims: Vec<_> = query
+            .iter()
+            .filter(|(dim, _)| allow.map(|allow| allow.contains(dim)).unwrap_or(true))
+            .filter(|(dim, _)| !deny.map(|deny| deny.contains(dim)).unwrap_or(false))
+            .collect();
+
+        self.knn(&Vector::create_new_raw(dims), k)
+    }
+
+    /// Returns `false` if searching for `query` can't have any result
+    #[inline]
+    fn can_search(&self, query: &Vector) -> bool {
+        !self.vector_store.is_empty() && !query.is_empty() && query.get_length() != 0.0
+    }
+
+    /// Returns the similarity of all documents sharing at least one dimension with `query`
+    #[inline]
+    fn score_candidates<'a>(&'a self, query: &'a Vector) -> impl Iterator<Item = (u32, f32)> + 'a {
+        let candidates = self.vector_store.get_in_dims_iter(query.vec_indices());
+        self.vector_store.score_all(query, candidates.into_iter())
+    }
+
+    /// Returns the similarity of `query` to each document in `ids` in the order of `ids`. This
+    /// allows re-ranking candidates found in a different way. IDs of documents which don't exist
+    /// are skipped
+    #[inline]
+    pub fn score_ids(&self, query: &Vector, ids: &[u32]) -> Vec<(u32, f32)> {
+        self.vector_store
+            .score_all(query, ids.iter().copied())
+            .collect()
+    }
+
+    /// Returns for each document in `ids` the amount of dimensions it shares with `query`. IDs of
+    /// documents which don't exist are skipped
+    pub fn match_counts(&self, query: &Vector, ids: &[u32]) -> Vec<(u32, usize)> {
+        ids.iter()
+            .filter_map(|id| {
+                let doc = self.vector_store.load_vector(*id as usize)?;
+                Some((*id, query.overlap_count(doc.vector())))
+            })
+            .collect()
+    }
+
+    /// Calculates a score for `doc` by summing up the products of all shared dimensions weighted
+    /// by their inverse document frequency. This makes matches of rare terms more important
+    pub fn score_idf_weighted(&self, query: &Vector, doc: &DocumentVector<D>) -> f32 {
+        query
+            .overlapping(doc.vector())
+            .map(|(dim, q_val, d_val)| q_val * d_val * self.idf(dim))
+            .sum()
+    }
+
+    /// Calculates the similarity between `query` and `doc` and additionally returns the terms
+    /// both have in common
+    pub fn score_explained(&self, query: &Vector, doc: &DocumentVector<D>) -> (f32, Vec<String>) {
+        let mut scalar = 0.0;
+        let mut shared = vec![];
+
+        for (dim, q_val, d_val) in query.overlapping(doc.vector()) {
+            scalar += q_val * d_val;
+            shared.extend(self.term_for_dim(dim as usize));
+        }
+
+        if scalar == 0.0 {
+            return (0.0, shared);
+        }
+
+        let score = scalar / (query.get_length() * doc.vector().get_length());
+        (score, shared)
+    }
+
+    /// Searches for the `k` documents most similar to `terms` and returns their IDs together with
+    /// their similarity and the terms of the query they contain, in descending order
+    pub fn search_terms_explained(&self, terms: &[&str], k: usize) -> Vec<(u32, f32, Vec<String>)> {
+        let query = match self.build_vector(terms, None) {
+            Some(query) => query,
+            None => return vec![],
+        };
+
+        self.knn(&query, k)
+            .into_iter()
+            .filter_map(|(id, score)| {
+                let doc = self.vector_store.load_vector(id as usize)?;
+                let (_, matched) = self.score_explained(&query, &doc);
+                Some((id, score, matched))
+            })
+            .collect()
+    }
+
+    /// Returns the inverse document frequency of the given dimension or `0.0` if the dimension
+    /// doesn't exist
+    fn idf(&self, dim: u32) -> f32 {
+        let df = match self.indexer.load_term(dim as usize) {
+            Some(term) if term.doc_frequency() > 0 => term.doc_frequency(),
+            _ => return 0.0,
+        };
+
+        (self.total_documents() as f32 / df as f32).log10()
+    }
+
+    /// Returns the inverse document frequency of each dimension, calculated like `idf` but in a
+    /// single pass over all terms. This allows weighting external vectors
+    pub fn idf_vector(&self) -> HashMap<u32, f32> {
+        let total_docs = self.total_documents() as f32;
+
+        self.indexer
+            .iter()
+            .enumerate()
+            .map(|(dim, term)| {
+                let idf = match term.doc_frequency() {
+                    0 => 0.0,
+                    df => (total_docs / df as f32).log10(),
+                };
+                (dim as u32, idf)
+            })
+            .collect()
+    }
+
+    /// Returns the total amount of documents used for the inverse document frequency. Falls back
+    /// to the amount of vectors for indexes which don't store it
+    #[inline]
+    fn total_documents(&self) -> usize {
+        match self.indexer.total_documents() {
+            0 => self.vector_store.len(),
+            n => n,
+        }
+    }
+
+    /// Checks whether all stored vectors can be decoded and all dimensions used by them have a
+    /// term. Both can only fail for corrupted indexes. Lookups of dangling dimensions, eg. in
+    /// `score_explained`, silently skip them
+    pub fn validate_consistency(&self) -> std::result::Result<(), Inconsistencies> {
+        let term_count = self.indexer.len() as u32;
+
+        let mut report = Inconsistencies::default();
+        for (id, doc) in self.vector_store.iter_checked().enumerate() {
+            match doc {
+                Ok(doc) => report
+                    .dangling_dims
+                    .extend(doc.vector().vec_indices().filter(|dim| *dim >= term_count)),
+                Err(_) => report.undecodable.push(id as u32),
+            }
+        }
+
+        if report.dangling_dims.is_empty() && report.undecodable.is_empty() {
+            return Ok(());
+        }
+
+        report.dangling_dims.sort_unstable();
+        report.dangling_dims.dedup();
+        Err(report)
+    }
+
     pub fn is_stopword_cust(&self, term: &str, threshold: f32) -> Option<bool> {
         let tot_docs = self.get_indexer().len() as f32;
         let term = self.get_indexer().find_term(term)?;
@@ -101,6 +582,27 @@ impl<D: Decodable, M> Index<D, M> {
         self.is_stopword_cust(term, 35.0)
     }
 
+    /// Returns all terms with a document frequency of at least the `p`-th percentile (`0.0` to
+    /// `100.0`) of the document frequencies of all terms. Other than a fixed threshold this
+    /// adapts to the distribution of the terms
+    pub fn stopwords_percentile(&self, p: f32) -> Vec<String> {
+        let mut dfs: Vec<_> = self.indexer.iter().map(|i| i.doc_frequency()).collect();
+        if dfs.is_empty() {
+            return vec![];
+        }
+        dfs.sort_unstable();
+
+        // Nearest rank method
+        let rank = (p.clamp(0.0, 100.0) / 100.0 * dfs.len() as f32).ceil() as usize;
+        let threshold = dfs[rank.saturating_sub(1)];
+
+        self.indexer
+            .iter()
+            .filter(|i| i.doc_frequency() >= threshold)
+            .map(|i| i.text().to_string())
+            .collect()
+    }
+
     /// Defragments the custom order mapping which has to be used to add new terms to the index.
     /// This can change indices of vectors which can become an issue if you use them outside to
     /// reference something.
@@ -130,17 +632,456 @@ impl<D: Decodable, M> Index<D, M> {
     }
 }
 
-impl<D: Decodable, M: DeserializeOwned + Serialize> Index<D, M> {
+impl<D: Decodable + Encodable, M> Index<D, M> {
+    /// Removes all documents for which `keep` returns `false` and rebuilds the vector store. The
+    /// IDs of the remaining documents change since they get renumbered. The document frequencies
+    /// of the terms and the total amount of documents get reduced by the removed documents
+    pub fn retain_documents<F: Fn(&DocumentVector<D>) -> bool>(&mut self, keep: F) -> Result<()> {
+        let store = &self.vector_store;
+        let mut vectors = vec![];
+        let mut ids = vec![];
+        let mut removed_docs = 0;
+        let mut removed_dfs: HashMap<u32, u32> = HashMap::new();
+        for (id, vec) in store.iter_with_ids() {
+            if keep(&vec) {
+                vectors.push(vec);
+                ids.push(id);
+                continue;
+            }
+
+            let multiplicity = store.multiplicity(id);
+            removed_docs += multiplicity as usize;
+            for dim in vec.vector().vec_indices() {
+                *removed_dfs.entry(dim).or_default() += multiplicity;
+            }
+        }
+
+        let lengths = ids.iter().map(|id| store.doc_length(*id));
+        let mut external_ids = vec![];
+        if !store.external_ids.is_empty() {
+            external_ids = ids
+                .iter()
+                .map(|id| self.external_id(*id as usize))
+                .collect();
+        }
+
+        let mut multiplicities = vec![];
+        if !store.multiplicities.is_empty() {
+            multiplicities = ids.iter().map(|id| store.multiplicity(*id)).collect();
+        }
+
+        let mut term_freqs = vec![];
+        if !store.term_freqs.is_empty() {
+            term_freqs = ids
+                .iter()
+                .map(|id| store.term_freqs(*id).unwrap_or_default().to_vec())
+                .collect();
+        }
+
+        // Indexes without document lengths don't have any length at all
+        let lengths = lengths
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default();
+
+        let total_documents = self.total_documents().saturating_sub(removed_docs);
+        self.indexer.set_total_documents(total_documents);
+        for (dim, count) in removed_dfs {
+            self.indexer.sub_doc_frequency(dim as usize, count);
+        }
+
+        let encoding = self.vector_store.encoding;
+        let gap_threshold = self.vector_store.map.gap_threshold();
+        self.vector_store = vector_store::build(vectors, lengths, encoding, gap_threshold)?;
+        self.vector_store.external_ids = external_ids;
+        self.vector_store.multiplicities = multiplicities;
+        self.vector_store.term_freqs = term_freqs;
+        Ok(())
+    }
+
+    /// Merges the dimensions of each group in `groups` into the groups lowest dimension, summing
+    /// up the values and term frequencies of documents containing multiple dimensions of a group.
+    /// The inverted index gets rebuilt afterwards. Terms and their document frequencies are not
+    /// adjusted
+    pub fn merge_dimensions(&mut self, groups: &[Vec<u32>]) -> Result<()> {
+        let mut mapping = HashMap::new();
+        for group in groups {
+            let canonical = match group.iter().min() {
+                Some(canonical) => *canonical,
+                None => continue,
+            };
+            for dim in group {
+                mapping.insert(*dim, canonical);
+            }
+        }
+
+        let vectors: Vec<_> = self
+            .vector_store
+            .iter()
+            .map(|mut doc| {
+                let mut merged = Vector::new_empty();
+                for (dim, val) in doc.vector().iter() {
+                    let dim = mapping.get(&dim).copied().unwrap_or(dim);
+                    merged.push_dim(dim, val, Conflict::Sum);
+                }
+                merged.update();
+                doc.set_vec(merged);
+                doc
+            })
+            .collect();
+
+        for term_freqs in self.vector_store.term_freqs.iter_mut() {
+            let mut merged: Vec<(u32, u32)> = vec![];
+            for (dim, tf) in term_freqs.iter() {
+                merged.push((mapping.get(dim).copied().unwrap_or(*dim), *tf));
+            }
+            merged.sort_unstable_by_key(|i| i.0);
+            merged.dedup_by(|a, b| {
+                let same = a.0 == b.0;
+                if same {
+                    b.1 += a.1;
+                }
+                same
+            });
+            *term_freqs = merged;
+        }
+
+        self.replace_vectors(vectors)
+    }
+
+    /// Recalculates the weights of all vectors using `weight`, passing the current values as
+    /// `current`. This requires the term frequencies of the documents, which are only stored in
+    /// indexes built using `IndexBuilder::with_term_freqs`. Returns `Error::MissingTermFreqs`
+    /// otherwise. Reweighting an index built with `NoWeight` equals building it using `weight`
+    /// directly
+    pub fn reweight(&mut self, weight: &dyn TermWeight) -> Result<()> {
+        let store = &self.vector_store;
+        if store.term_freqs.len() != store.len() {
+            return Err(Error::MissingTermFreqs);
+        }
+
+        let total_docs = self.total_documents();
+        let doc_freqs: Vec<_> = self
+            .indexer
+            .iter()
+            .map(|i| i.doc_frequency() as usize)
+            .collect();
+
+        let vectors: Vec<_> = store
+            .iter_with_ids()
+            .map(|(id, mut doc)| {
+                let term_freqs = store.term_freqs(id).unwrap_or_default();
+                let weighted = doc
+                    .vector()
+                    .iter()
+                    .map(|(dim, current)| {
+                        let tf = match term_freqs.binary_search_by_key(&dim, |i| i.0) {
+                            Ok(pos) => term_freqs[pos].1 as usize,
+                            Err(_) => 0,
+                        };
+                        let df = doc_freqs.get(dim as usize).copied().unwrap_or(0);
+                        (dim, weight.weight(current, tf, df, total_docs))
+                    })
+                    .collect();
+                doc.set_vec(Vector::create_new_raw(weighted));
+                doc
+            })
+            .collect();
+
+        self.replace_vectors(vectors)
+    }
+
+    /// Replaces all vectors of the vector store with `vectors` while keeping the per document
+    /// data like lengths and external IDs. The order of the documents must not change
+    fn replace_vectors(&mut self, vectors: Vec<DocumentVector<D>>) -> Result<()> {
+        let lengths = std::mem::take(&mut self.vector_store.lengths);
+        let external_ids = std::mem::take(&mut self.vector_store.external_ids);
+        let multiplicities = std::mem::take(&mut self.vector_store.multiplicities);
+        let term_freqs = std::mem::take(&mut self.vector_store.term_freqs);
+        let encoding = self.vector_store.encoding;
+        let gap_threshold = self.vector_store.map.gap_threshold();
+        self.vector_store = vector_store::build(vectors, lengths, encoding, gap_threshold)?;
+        self.vector_store.external_ids = external_ids;
+        self.vector_store.multiplicities = multiplicities;
+        self.vector_store.term_freqs = term_freqs;
+        Ok(())
+    }
+}
+
+impl<D: Decodable, M: Metadata + DeserializeOwned + Serialize> Index<D, M> {
     /// Opens an Index from a tar.gz file and returns a new `Index`
     #[inline]
     pub fn open<P: AsRef<Path>>(file: P) -> Result<Index<D, M>> {
         Self::from_reader(BufReader::new(File::open(file)?))
     }
 
-    /// Read an index-archive and build an `Index` out of it
+    /// Like `open` but reads the file using a buffer of `buf_capacity` bytes. A bigger buffer can
+    /// speed up opening large indexes
     #[inline]
-    pub fn from_reader<R: Read>(reader: R) -> Result<Index<D, M>> {
-        bincode::deserialize_from(reader).map_err(|_| Error::InvalidIndex)
+    pub fn open_with_capacity<P: AsRef<Path>>(file: P, buf_capacity: usize) -> Result<Index<D, M>> {
+        Self::from_reader(BufReader::with_capacity(buf_capacity, File::open(file)?))
+    }
+
+    /// Opens an Index by memory-mapping the file instead of reading it into a buffer first.
+    ///
+    /// # Safety
+    /// The file gets mapped into memory while being decoded. It must not be modified or truncated
+    /// (by this or any other process) until this function returns, otherwise the behaviour is
+    /// undefined. The returned `Index` doesn't reference the mapping anymore.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn open_mmap<P: AsRef<Path>>(file: P) -> Result<Index<D, M>> {
+        let file = File::open(file)?;
+        let mmap = memmap2::Mmap::map(&file)?;
+        Self::from_reader(&mmap[..])
+    }
+
+    /// Read an index-archive and build an `Index` out of it. Since bincode relies on the layout
+    /// of the structs, the version of the metadata decides how the rest gets read. Indexes of
+    /// `IndexVersion::V1` use the layout from before document lengths were stored
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Index<D, M>> {
+        // Same options as `bincode::deserialize_from`
+        let options = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes();
+
+        let metadata: M = options.deserialize_from(&mut reader)?;
+        let (indexer, vector_store): (TermIndexer, _) = match metadata.get_version() {
+            IndexVersion::V1 => {
+                let indexer = options.deserialize_from::<_, LegacyTermIndexer>(&mut reader)?;
+                let vector_store =
+                    options.deserialize_from::<_, LegacyVectorStore<D>>(&mut reader)?;
+                (indexer.into(), vector_store.into())
+            }
+            _ => (
+                options.deserialize_from(&mut reader)?,
+                options.deserialize_from(&mut reader)?,
+            ),
+        };
+
+        // Terms get decoded without checking them when looking them up
+        indexer.validate()?;
+
+        Ok(Index {
+            metadata,
+            indexer,
+            vector_store,
+        })
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl<D, M> Index<D, M>
+where
+    D: Decodable + Encodable + Serialize + DeserializeOwned,
+    M: Serialize + DeserializeOwned,
+{
+    /// Writes the index as JSON into `out`. This is meant for small indexes, eg. test fixtures,
+    /// which should be human readable. The inverted index isn't included and gets rebuilt by
+    /// `Index::from_json`
+    pub fn to_json<W: Write>(&self, out: W) -> Result<()> {
+        let json = JsonIndex {
+            metadata: &self.metadata,
+            total_documents: self.indexer.total_documents(),
+            terms: self
+                .indexer
+                .iter()
+                .map(|i| (i.text().to_string(), i.doc_frequency()))
+                .collect(),
+            documents: self
+                .vector_store
+                .iter()
+                .map(|i| {
+                    let vec = i.vector().sparse_vec().clone();
+                    (i.document, vec)
+                })
+                .collect(),
+            lengths: self.vector_store.lengths.clone(),
+            external_ids: self.vector_store.external_ids.clone(),
+            multiplicities: self.vector_store.multiplicities.clone(),
+            term_freqs: self.vector_store.term_freqs.clone(),
+            without_term_text: !self.indexer.has_text(),
+        };
+        serde_json::to_writer(out, &json)?;
+        Ok(())
+    }
+
+    /// Reads an index written by `Index::to_json`
+    pub fn from_json<R: Read>(reader: R) -> Result<Self> {
+        let json: JsonIndex<D, M> = serde_json::from_reader(reader)?;
+
+        let terms = json
+            .terms
+            .into_iter()
+            .map(|(text, doc_freq)| IndexTerm::new(text, doc_freq))
+            .collect();
+        let mut indexer = TermIndexer::from_terms(terms, json.total_documents)?;
+        indexer.set_has_text(!json.without_term_text);
+
+        let vectors = json
+            .documents
+            .into_iter()
+            .map(|(doc, vec)| DocumentVector::new(doc, Vector::create_new_raw(vec)))
+            .collect();
+        let mut vector_store = vector_store::build(
+            vectors,
+            json.lengths,
+            VectorEncoding::default(),
+            crate::inv_index::DEFAULT_GAP_THRESHOLD,
+        )?;
+        vector_store.external_ids = json.external_ids;
+        vector_store.multiplicities = json.multiplicities;
+        vector_store.term_freqs = json.term_freqs;
+
+        Ok(Self {
+            metadata: json.metadata,
+            indexer,
+            vector_store,
+        })
+    }
+}
+
+impl<D: Decodable, M: Metadata> Index<D, M> {
+    /// Writes the index in a versioned format which doesn't depend on the layout of the structs.
+    /// Each component is written into its own section prefixed with the sections ID and length.
+    /// The index can be read again using `Index::read_from`
+    pub fn write_to<W: Write>(&self, mut out: W) -> Result<()> {
+        out.write_all(FORMAT_MAGIC)?;
+        out.write_u8(FORMAT_VERSION)?;
+
+        let metadata = self.metadata.encode::<LittleEndian>()?;
+        write_section(&mut out, SECTION_METADATA, &metadata)?;
+
+        let terms = self.indexer.encode_front_coded::<LittleEndian>()?;
+        write_section(&mut out, SECTION_TERMS, &terms)?;
+
+        if !self.indexer.has_text() {
+            write_section(&mut out, SECTION_NO_TERM_TEXT, &[])?;
+        }
+
+        let inv_index = self.vector_store.map.encode::<LittleEndian>()?;
+        write_section(&mut out, SECTION_INV_INDEX, &inv_index)?;
+
+        let vectors = self.vector_store.store.encode::<LittleEndian>()?;
+        write_section(&mut out, SECTION_VECTORS, &vectors)?;
+
+        let lengths = self.vector_store.lengths.encode::<LittleEndian>()?;
+        write_section(&mut out, SECTION_DOC_LENGTHS, &lengths)?;
+
+        let encoding = [self.vector_store.encoding as u8];
+        write_section(&mut out, SECTION_VEC_ENCODING, &encoding)?;
+
+        if !self.vector_store.external_ids.is_empty() {
+            let external_ids = encode_external_ids(&self.vector_store.external_ids)?;
+            write_section(&mut out, SECTION_EXTERNAL_IDS, &external_ids)?;
+        }
+
+        if !self.vector_store.multiplicities.is_empty() {
+            let multiplicities = self.vector_store.multiplicities.encode::<LittleEndian>()?;
+            write_section(&mut out, SECTION_MULTIPLICITIES, &multiplicities)?;
+        }
+
+        if !self.vector_store.term_freqs.is_empty() {
+            let term_freqs = encode_term_freqs(&self.vector_store.term_freqs)?;
+            write_section(&mut out, SECTION_TERM_FREQS, &term_freqs)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads an index written by `Index::write_to`. The order of the sections doesn't matter and
+    /// unknown sections get skipped
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Index<D, M>> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        let version = reader.read_u8()?;
+        if &magic != FORMAT_MAGIC || version == 0 || version > FORMAT_VERSION {
+            return Err(Error::InvalidIndex);
+        }
+
+        let mut metadata = None;
+        let mut indexer = None;
+        let mut inv_index = None;
+        let mut vectors = None;
+        // Optional since older indexes don't have document lengths
+        let mut lengths = vec![];
+        // Optional since older indexes always use the packed encoding
+        let mut encoding = VectorEncoding::Packed;
+        let mut external_ids = vec![];
+        let mut multiplicities = vec![];
+        let mut term_freqs = vec![];
+        let mut has_term_text = true;
+
+        loop {
+            let section = match reader.read_u8() {
+                Ok(section) => section,
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            };
+
+            // Don't trust the length for allocating since it might be corrupted
+            let len = reader.read_u64::<LittleEndian>()?;
+            let mut data = vec![];
+            (&mut reader).take(len).read_to_end(&mut data)?;
+            if data.len() as u64 != len {
+                return Err(Error::InvalidIndex);
+            }
+            let data = data.as_slice();
+
+            match section {
+                SECTION_METADATA => metadata = Some(M::decode::<LittleEndian, _>(data)?),
+                SECTION_TERMS if version == 1 => {
+                    indexer = Some(TermIndexer::decode::<LittleEndian, _>(data)?)
+                }
+                SECTION_TERMS => {
+                    indexer = Some(TermIndexer::decode_front_coded::<LittleEndian, _>(data)?)
+                }
+                SECTION_INV_INDEX => {
+                    inv_index = Some(InvertedIndex::decode::<LittleEndian, _>(data)?)
+                }
+                SECTION_VECTORS => vectors = Some(MemFile::decode::<LittleEndian, _>(data)?),
+                SECTION_DOC_LENGTHS => lengths = Vec::<u32>::decode::<LittleEndian, _>(data)?,
+                SECTION_VEC_ENCODING => {
+                    encoding = VectorEncoding::try_from(data.first().copied().unwrap_or(0))?
+                }
+                SECTION_EXTERNAL_IDS => external_ids = decode_external_ids(data)?,
+                SECTION_MULTIPLICITIES => {
+                    multiplicities = Vec::<u32>::decode::<LittleEndian, _>(data)?
+                }
+                SECTION_TERM_FREQS => term_freqs = decode_term_freqs(data)?,
+                SECTION_NO_TERM_TEXT => has_term_text = false,
+                _ => (),
+            }
+        }
+
+        let (metadata, mut indexer, inv_index, vectors) =
+            match (metadata, indexer, inv_index, vectors) {
+                (Some(metadata), Some(indexer), Some(inv_index), Some(vectors)) => {
+                    (metadata, indexer, inv_index, vectors)
+                }
+                _ => return Err(Error::InvalidIndex),
+            };
+
+        indexer.set_has_text(has_term_text);
+
+        let mut vector_store = VectorStore::from_parts(vectors, inv_index, lengths, encoding);
+        vector_store.multiplicities = multiplicities;
+        vector_store.term_freqs = term_freqs;
+        if !external_ids.is_empty() {
+            vector_store.external_ids = vec![None; vector_store.len()];
+            for (internal, external) in external_ids {
+                let id = vector_store
+                    .external_ids
+                    .get_mut(internal as usize)
+                    .ok_or(Error::InvalidIndex)?;
+                *id = Some(external);
+            }
+        }
+
+        Ok(Index {
+            metadata,
+            indexer,
+            vector_store,
+        })
     }
 }
 
@@ -160,9 +1101,82 @@ impl<D: Decodable, M: Clone> Index<D, M> {
     }
 }
 
-impl<D: Decodable, M: Metadata + Default> Default for Index<D, M> {
-    #[inline]
-    fn default() -> Self {
+impl<D: Decodable + PartialEq, M> Index<D, M> {
+    /// Compares the index with `other` and returns the terms only one of them contains and all
+    /// documents which differ. Vectors are compared using the terms of their dimensions, so both
+    /// indexes can have a different dimension order.
+    pub fn diff<O>(&self, other: &Index<D, O>) -> IndexDiff {
+        let mut diff = IndexDiff::default();
+
+        let terms_a = self
+            .indexer
+            .iter_sorted()
+            .map(|i| (i.text().to_string(), ()));
+        let terms_b = other
+            .indexer
+            .iter_sorted()
+            .map(|i| (i.text().to_string(), ()));
+        for (term, a, b) in LockStepOuterIter::new(terms_a, terms_b) {
+            match (a, b) {
+                (Some(_), None) => diff.terms_only_in_self.push(term),
+                (None, Some(_)) => diff.terms_only_in_other.push(term),
+                _ => (),
+            }
+        }
+
+        let docs_a = self.vector_store.iter_with_ids();
+        let docs_b = other.vector_store.iter_with_ids();
+        for (id, a, b) in LockStepOuterIter::new(docs_a, docs_b) {
+            let equal = match (a, b) {
+                (Some(a), Some(b)) => {
+                    a.document == b.document
+                        && self.term_weights(a.vector()) == other.term_weights(b.vector())
+                }
+                _ => false,
+            };
+            if !equal {
+                diff.changed_documents.push(id);
+            }
+        }
+
+        diff
+    }
+
+    /// Returns the terms and weights of `vec` sorted by the terms
+    fn term_weights(&self, vec: &Vector) -> Vec<(String, f32)> {
+        let mut terms: Vec<_> = vec
+            .iter()
+            .map(|(dim, val)| (self.term_for_dim(dim as usize).unwrap_or_default(), val))
+            .collect();
+        terms.sort_by(|a, b| a.0.cmp(&b.0));
+        terms
+    }
+}
+
+/// Differences between two indexes. See `Index::diff`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexDiff {
+    /// Terms only contained in the index `diff` was called on
+    pub terms_only_in_self: Vec<String>,
+    /// Terms only contained in the index passed to `diff`
+    pub terms_only_in_other: Vec<String>,
+    /// IDs of documents which differ or only exist in one of both indexes
+    pub changed_documents: Vec<u32>,
+}
+
+impl IndexDiff {
+    /// Returns `true` if both indexes contain the same terms and documents
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.terms_only_in_self.is_empty()
+            && self.terms_only_in_other.is_empty()
+            && self.changed_documents.is_empty()
+    }
+}
+
+impl<D: Decodable, M: Metadata + Default> Default for Index<D, M> {
+    #[inline]
+    fn default() -> Self {
         Self {
             metadata: Default::default(),
             indexer: Default::default(),
@@ -171,6 +1185,159 @@ impl<D: Decodable, M: Metadata + Default> Default for Index<D, M> {
     }
 }
 
+/// Layout of an index written by `Index::to_json`
+#[cfg(feature = "serde_json")]
+#[derive(Serialize, Deserialize)]
+struct JsonIndex<D, M> {
+    metadata: M,
+    total_documents: usize,
+    /// Terms ordered by their dimension as (text, document frequency)
+    terms: Vec<(String, u32)>,
+    /// Documents and their sparse vectors
+    documents: Vec<(D, Vec<(u32, f32)>)>,
+    #[serde(default)]
+    lengths: Vec<u32>,
+    #[serde(default)]
+    external_ids: Vec<Option<u64>>,
+    #[serde(default)]
+    multiplicities: Vec<u32>,
+    #[serde(default)]
+    term_freqs: Vec<Vec<(u32, u32)>>,
+    #[serde(default)]
+    without_term_text: bool,
+}
+
+/// Problems of a corrupted index found by `Index::validate_consistency`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Inconsistencies {
+    /// Sorted dimensions used by vectors which exceed the amount of terms
+    pub dangling_dims: Vec<u32>,
+    /// IDs of the vectors which can't be decoded
+    pub undecodable: Vec<u32>,
+}
+
+/// A scored document ID which is ordered by its score. Equal scores are ordered by the ID with
+/// lower IDs being greater
+#[derive(Clone, Copy, PartialEq)]
+struct Scored(f32, u32);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .partial_cmp(&other.0)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.1.cmp(&self.1))
+    }
+}
+
+/// Returns the `k` items with the highest scores in descending order
+pub(crate) fn top_k<I: Iterator<Item = (u32, f32)>>(items: I, k: usize) -> Vec<(u32, f32)> {
+    let items = items.map(|(id, score)| (id, score, ()));
+    top_k_with(items, k)
+        .into_iter()
+        .map(|(id, score, _)| (id, score))
+        .collect()
+}
+
+/// Like `top_k` but keeps the additional data of each item
+pub(crate) fn top_k_with<T: Ord, I: Iterator<Item = (u32, f32, T)>>(
+    items: I,
+    k: usize,
+) -> Vec<(u32, f32, T)> {
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+
+    for (id, score, data) in items {
+        heap.push((Reverse(Scored(score, id)), data));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+
+    let mut res: Vec<_> = heap
+        .into_iter()
+        .map(|(Reverse(Scored(score, id)), data)| (id, score, data))
+        .collect();
+    res.sort_by_key(|i| Reverse(Scored(i.1, i.0)));
+    res
+}
+
+/// Sorts scored IDs by their score in descending order
+#[inline]
+fn sort_scored(items: &mut [(u32, f32)]) {
+    items.sort_by_key(|i| Reverse(Scored(i.1, i.0)));
+}
+
+/// Writes a single section of the format used by `Index::write_to`
+fn write_section<W: Write>(mut out: W, section: u8, data: &[u8]) -> Result<()> {
+    out.write_u8(section)?;
+    out.write_u64::<LittleEndian>(data.len() as u64)?;
+    out.write_all(data)?;
+    Ok(())
+}
+
+/// Encodes the external IDs of the documents having one as pairs of (ID, external ID)
+fn encode_external_ids(external_ids: &[Option<u64>]) -> Result<Vec<u8>> {
+    let mut out = vec![];
+    for (id, external) in external_ids.iter().enumerate() {
+        if let Some(external) = external {
+            out.write_u32::<LittleEndian>(id as u32)?;
+            out.write_u64::<LittleEndian>(*external)?;
+        }
+    }
+    Ok(out)
+}
+
+/// Decodes the pairs of (ID, external ID) written by `encode_external_ids`
+fn decode_external_ids(mut data: &[u8]) -> Result<Vec<(u32, u64)>> {
+    let mut out = Vec::with_capacity(data.len() / 12);
+    while !data.is_empty() {
+        let id = data.read_u32::<LittleEndian>()?;
+        let external = data.read_u64::<LittleEndian>()?;
+        out.push((id, external));
+    }
+    Ok(out)
+}
+
+/// Encodes the term frequencies of each document as the amount of terms followed by pairs of
+/// (dimension, term frequency)
+fn encode_term_freqs(term_freqs: &[Vec<(u32, u32)>]) -> Result<Vec<u8>> {
+    let mut out = vec![];
+    for doc in term_freqs {
+        out.write_u32::<LittleEndian>(doc.len() as u32)?;
+        for (dim, tf) in doc {
+            out.write_u32::<LittleEndian>(*dim)?;
+            out.write_u32::<LittleEndian>(*tf)?;
+        }
+    }
+    Ok(out)
+}
+
+/// Decodes the term frequencies of each document written by `encode_term_freqs`
+fn decode_term_freqs(mut data: &[u8]) -> Result<Vec<Vec<(u32, u32)>>> {
+    let mut out = vec![];
+    while !data.is_empty() {
+        let len = data.read_u32::<LittleEndian>()? as usize;
+        let mut doc = Vec::with_capacity(len.min(data.len() / 8));
+        for _ in 0..len {
+            let dim = data.read_u32::<LittleEndian>()?;
+            let tf = data.read_u32::<LittleEndian>()?;
+            doc.push((dim, tf));
+        }
+        out.push(doc);
+    }
+    Ok(out)
+}
+
 #[inline]
 fn serialize_vs<D: Decodable, S>(v: &VectorStore<D>, ser: S) -> std::result::Result<S::Ok, S::Error>
 where
@@ -190,3 +1357,963 @@ where
     let o: VectorStore<D> = Deserialize::deserialize(de)?;
     Ok(o)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{build::IndexBuilder, DefaultMetadata};
+    use byteorder::ByteOrder;
+
+    const DOCUMENTS: &[&[&str]] = &[
+        &["to", "drive", "a", "car"],
+        &["to", "have", "a", "call"],
+        &["to", "make", "a", "stand", "a"],
+    ];
+
+    fn build_index() -> Index<u32, DefaultMetadata> {
+        let mut builder = IndexBuilder::new();
+        for (pos, terms) in DOCUMENTS.iter().enumerate() {
+            builder.insert_new_vec(pos as u32, terms);
+        }
+        builder.build(DefaultMetadata::default()).unwrap()
+    }
+
+    #[test]
+    fn test_open_with_capacity() {
+        let path = std::env::temp_dir().join("vsm_test_open_with_capacity");
+        let index = build_index();
+        bincode::serialize_into(File::create(&path).unwrap(), &index).unwrap();
+
+        let opened = Index::<u32, DefaultMetadata>::open_with_capacity(&path, 1 << 20).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            bincode::serialize(&opened).unwrap(),
+            bincode::serialize(&index).unwrap()
+        );
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_open_mmap() {
+        let path = std::env::temp_dir().join("vsm_test_open_mmap");
+        let index = build_index();
+        bincode::serialize_into(File::create(&path).unwrap(), &index).unwrap();
+
+        let opened = Index::<u32, DefaultMetadata>::open(&path).unwrap();
+        let mapped = unsafe { Index::<u32, DefaultMetadata>::open_mmap(&path).unwrap() };
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            bincode::serialize(&opened).unwrap(),
+            bincode::serialize(&mapped).unwrap()
+        );
+        assert_eq!(mapped.get_vector_store().len(), DOCUMENTS.len());
+    }
+
+    #[test]
+    fn test_term_dim_mapping() {
+        let index = build_index();
+
+        for term in DOCUMENTS.iter().flat_map(|i| i.iter()) {
+            let dim = index.dim_for_term(term).unwrap();
+            assert_eq!(index.term_for_dim(dim).as_deref(), Some(*term));
+        }
+
+        for dim in 0..index.get_indexer().len() {
+            let term = index.term_for_dim(dim).unwrap();
+            assert_eq!(index.dim_for_term(&term), Some(dim));
+        }
+
+        assert_eq!(index.dim_for_term("unknown"), None);
+        assert_eq!(index.term_for_dim(index.get_indexer().len()), None);
+    }
+
+    #[cfg(feature = "genbktree")]
+    #[test]
+    fn test_suggest() {
+        let mut builder = IndexBuilder::new();
+        builder.insert_new_vec(0u32, &["house", "big"]);
+        builder.insert_new_vec(1u32, &["house", "small"]);
+        builder.insert_new_vec(2u32, &["horse", "big"]);
+        let index: Index<u32, DefaultMetadata> = builder.build(DefaultMetadata::default()).unwrap();
+
+        assert_eq!(index.suggest("hovse", 1), vec!["house", "horse"]);
+        assert_eq!(index.suggest("bigg", 1), vec!["big"]);
+        assert!(index.suggest("elephant", 2).is_empty());
+    }
+
+    #[test]
+    fn test_score_idf_weighted() {
+        let mut builder = IndexBuilder::new();
+        builder.insert_new_vec(0u32, &["rare", "common"]);
+        builder.insert_new_vec(1u32, &["common", "a"]);
+        builder.insert_new_vec(2u32, &["common", "b"]);
+        builder.insert_new_vec(3u32, &["c"]);
+        let index: Index<u32, DefaultMetadata> = builder.build(DefaultMetadata::default()).unwrap();
+
+        let rare = index.dim_for_term("rare").unwrap() as u32;
+        let common = index.dim_for_term("common").unwrap() as u32;
+
+        let query = index
+            .build_vector_weights(&[("rare", 1.0), ("common", 1.0)])
+            .unwrap();
+        let rare_doc = DocumentVector::new(0u32, Vector::create_new_raw(vec![(rare, 1.0)]));
+        let common_doc = DocumentVector::new(1u32, Vector::create_new_raw(vec![(common, 1.0)]));
+
+        assert_eq!(
+            query.similarity(rare_doc.vector()),
+            query.similarity(common_doc.vector())
+        );
+        assert!(
+            index.score_idf_weighted(&query, &rare_doc)
+                > index.score_idf_weighted(&query, &common_doc)
+        );
+    }
+
+    #[test]
+    fn test_score_explained() {
+        let index = build_index();
+        let query = index.build_vector(&["drive", "a", "stand"], None).unwrap();
+        let doc = index.get_vector_store().load_vector(0).unwrap();
+
+        let (score, mut shared) = index.score_explained(&query, &doc);
+        shared.sort();
+
+        assert_eq!(shared, vec!["a", "drive"]);
+        assert!((score - query.similarity(doc.vector())).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_retain_documents() {
+        let mut index = build_index();
+        let drive = index.dim_for_term("drive").unwrap() as u32;
+
+        index
+            .retain_documents(|doc| doc.vector().has_dim(drive))
+            .unwrap();
+        assert_eq!(index.get_vector_store().len(), 1);
+
+        // Document frequencies and the total amount of documents only count remaining documents
+        assert_eq!(index.get_indexer().total_documents(), 1);
+        let df = |term: &str| index.get_indexer().find_term(term).unwrap().doc_frequency();
+        assert_eq!(df("a"), 1);
+        assert_eq!(df("call"), 0);
+
+        let query = index.build_vector(&["a"], None).unwrap();
+        let res: Vec<_> = index
+            .get_vector_store()
+            .get_for_vec(&query)
+            .map(|i| i.document)
+            .collect();
+        assert_eq!(res, vec![0]);
+    }
+
+    fn assert_same_index(a: &Index<u32, DefaultMetadata>, b: &Index<u32, DefaultMetadata>) {
+        assert_eq!(a.metadata.version, b.metadata.version);
+        assert_eq!(a.metadata.document_count, b.metadata.document_count);
+
+        let terms = |index: &Index<u32, DefaultMetadata>| -> Vec<_> {
+            index
+                .get_indexer()
+                .iter()
+                .map(|i| (i.text().to_string(), i.doc_frequency()))
+                .collect()
+        };
+        assert_eq!(terms(a), terms(b));
+
+        let vectors = |index: &Index<u32, DefaultMetadata>| -> Vec<_> {
+            index
+                .get_vector_store()
+                .iter()
+                .map(|i| (i.document, i.vector().sparse_vec().clone()))
+                .collect()
+        };
+        assert_eq!(vectors(a), vectors(b));
+
+        assert_eq!(
+            a.get_vector_store().get_map().decoded_btree(),
+            b.get_vector_store().get_map().decoded_btree()
+        );
+        assert_eq!(a.get_vector_store().lengths, b.get_vector_store().lengths);
+    }
+
+    #[test]
+    fn test_write_read() {
+        let mut index = build_index();
+        index.get_metadata_mut().set_document_count(DOCUMENTS.len());
+
+        let mut out = vec![];
+        index.write_to(&mut out).unwrap();
+
+        let read = Index::<u32, DefaultMetadata>::read_from(out.as_slice()).unwrap();
+        assert_same_index(&index, &read);
+
+        assert!(!read.get_vector_store().is_quantized());
+
+        assert!(Index::<u32, DefaultMetadata>::read_from(&out[1..]).is_err());
+        assert!(Index::<u32, DefaultMetadata>::read_from(&out[..out.len() - 1]).is_err());
+
+        // A corrupted section length must not be used for allocating
+        let mut corrupted = out[..5].to_vec();
+        corrupted.push(SECTION_METADATA);
+        corrupted.extend(u64::MAX.to_le_bytes());
+        corrupted.extend(&out[14..]);
+        assert!(matches!(
+            Index::<u32, DefaultMetadata>::read_from(corrupted.as_slice()),
+            Err(Error::InvalidIndex)
+        ));
+    }
+
+    #[test]
+    fn test_from_reader_legacy() {
+        // Layout written by bincode before document lengths were stored
+        #[derive(Serialize)]
+        struct Legacy<'a> {
+            metadata: DefaultMetadata,
+            indexer: LegacyTermIndexer,
+            store: &'a MemFile,
+            map: crate::inv_index::LegacyInvertedIndex,
+        }
+
+        let mut builder = IndexBuilder::new().with_gap_threshold(u32::MAX);
+        for (pos, terms) in DOCUMENTS.iter().enumerate() {
+            builder.insert_new_vec(pos as u32, terms);
+        }
+        let mut index = builder
+            .build(DefaultMetadata::new(crate::metadata::IndexVersion::V1))
+            .unwrap();
+
+        let legacy = Legacy {
+            metadata: index.metadata,
+            indexer: (&index.indexer).into(),
+            store: &index.vector_store.store,
+            map: (&index.vector_store.map).into(),
+        };
+        let out = bincode::serialize(&legacy).unwrap();
+        let read = Index::<u32, DefaultMetadata>::from_reader(out.as_slice()).unwrap();
+        assert_eq!(read.get_vector_store().doc_length(0), None);
+
+        index.vector_store.lengths.clear();
+        assert_same_index(&index, &read);
+
+        // Indexes with the current layout are read as they are
+        index.metadata = DefaultMetadata::default();
+        let out = bincode::serialize(&index).unwrap();
+        let read = Index::<u32, DefaultMetadata>::from_reader(out.as_slice()).unwrap();
+        assert_same_index(&index, &read);
+        assert!(matches!(
+            Index::<u32, DefaultMetadata>::from_reader(&out[..out.len() - 1]),
+            Err(Error::Bincode(_))
+        ));
+
+        // Trailing data is ignored like `bincode::deserialize_from` does
+        let mut trailing = out.clone();
+        trailing.push(0);
+        let read = Index::<u32, DefaultMetadata>::from_reader(trailing.as_slice()).unwrap();
+        assert_same_index(&index, &read);
+    }
+
+    #[test]
+    fn test_read_reordered_fields() {
+        // Same components as `Index` but in a different order
+        #[derive(Serialize)]
+        struct Reordered<'a> {
+            vector_store: &'a VectorStore<u32>,
+            indexer: &'a TermIndexer,
+            metadata: &'a DefaultMetadata,
+        }
+
+        let index = build_index();
+        let reordered = Reordered {
+            vector_store: &index.vector_store,
+            indexer: &index.indexer,
+            metadata: &index.metadata,
+        };
+
+        // Bincode relies on the order of the fields
+        let out = bincode::serialize(&reordered).unwrap();
+        assert!(Index::<u32, DefaultMetadata>::from_reader(out.as_slice()).is_err());
+
+        // Sections are identified by their ID, so writing them in the order of the fields works
+        let vector_store = reordered.vector_store;
+        let mut out = FORMAT_MAGIC.to_vec();
+        out.push(FORMAT_VERSION);
+        for (section, data) in [
+            (SECTION_VECTORS, vector_store.store.encode::<LittleEndian>()),
+            (SECTION_INV_INDEX, vector_store.map.encode::<LittleEndian>()),
+            (
+                SECTION_DOC_LENGTHS,
+                vector_store.lengths.encode::<LittleEndian>(),
+            ),
+            (SECTION_VEC_ENCODING, Ok(vec![vector_store.encoding as u8])),
+            (
+                SECTION_TERMS,
+                reordered.indexer.encode_front_coded::<LittleEndian>(),
+            ),
+            (
+                SECTION_METADATA,
+                reordered.metadata.encode::<LittleEndian>(),
+            ),
+        ]
+        .iter()
+        {
+            write_section(&mut out, *section, data.as_ref().unwrap()).unwrap();
+        }
+
+        let read = Index::<u32, DefaultMetadata>::read_from(out.as_slice()).unwrap();
+        assert_same_index(&index, &read);
+    }
+
+    #[test]
+    #[cfg(feature = "serde_json")]
+    fn test_json() {
+        let mut index = build_index();
+        index.get_metadata_mut().set_document_count(DOCUMENTS.len());
+
+        let mut out = vec![];
+        index.to_json(&mut out).unwrap();
+
+        let read = Index::<u32, DefaultMetadata>::from_json(out.as_slice()).unwrap();
+        assert_same_index(&index, &read);
+        assert!(index.diff(&read).is_empty());
+
+        assert!(Index::<u32, DefaultMetadata>::from_json(&out[1..]).is_err());
+    }
+
+    #[test]
+    fn test_build_vector_pruned() {
+        let index = build_index();
+        let candidates = |query: &Vector| {
+            index
+                .get_vector_store()
+                .get_in_dims_iter(query.vec_indices())
+                .len()
+        };
+
+        let full = index.build_vector(&["to", "drive"], None).unwrap();
+        assert_eq!(candidates(&full), 3);
+
+        let pruned = index.build_vector_pruned(&["to", "drive"], 0.5).unwrap();
+        assert_eq!(candidates(&pruned), 1);
+        assert_eq!(index.knn(&pruned, 3)[0].0, 0);
+
+        assert!(index.build_vector_pruned(&["to", "a"], 0.5).is_none());
+        assert!(index.build_vector_pruned(&["to", "a"], 1.0).is_some());
+    }
+
+    #[test]
+    fn test_knn_with_coverage() {
+        let index = build_index();
+        let query = index.build_vector(&["drive", "a", "call"], None).unwrap();
+
+        let res = index.knn_with_coverage(&query, 2);
+        let knn = index.knn(&query, 2);
+        assert_eq!(res.len(), 2);
+
+        for ((id, sim, coverage), (knn_id, knn_sim)) in res.into_iter().zip(knn) {
+            assert_eq!(id, knn_id);
+            assert!((sim - knn_sim).abs() < 0.0001);
+
+            let doc = index.get_vector_store().load_vector(id as usize).unwrap();
+            assert_eq!(coverage, query.overlap_count(doc.vector()));
+            assert_eq!(coverage, 2);
+        }
+    }
+
+    #[test]
+    fn test_idf_vector() {
+        let index = build_index();
+        let idf = index.idf_vector();
+        assert_eq!(idf.len(), index.get_indexer().len());
+
+        let car = index.dim_for_term("car").unwrap() as u32;
+        let to = index.dim_for_term("to").unwrap() as u32;
+        assert!(idf[&car] > idf[&to]);
+        assert_eq!(idf[&to], 0.0);
+        assert_eq!(idf[&car], index.idf(car));
+    }
+
+    #[test]
+    fn test_validate_consistency() {
+        let mut index = build_index();
+        assert!(index.validate_consistency().is_ok());
+
+        let term_count = index.get_indexer().len() as u32;
+        let mut vectors: Vec<_> = index.get_vector_store().iter().collect();
+        let dims = vec![(0, 1.0), (term_count + 5, 1.0), (term_count, 2.0)];
+        vectors[1].set_vec(Vector::create_new_raw(dims));
+        index.vector_store = vector_store::build(
+            vectors,
+            vec![],
+            VectorEncoding::Packed,
+            crate::inv_index::DEFAULT_GAP_THRESHOLD,
+        )
+        .unwrap();
+
+        let dangling_dims = vec![term_count, term_count + 5];
+        assert_eq!(
+            index.validate_consistency(),
+            Err(Inconsistencies {
+                dangling_dims: dangling_dims.clone(),
+                undecodable: vec![],
+            })
+        );
+
+        // Undecodable vectors get reported instead of panicking
+        let id = index.vector_store.store.insert(&[1u8]) as u32;
+        assert_eq!(
+            index.validate_consistency(),
+            Err(Inconsistencies {
+                dangling_dims,
+                undecodable: vec![id],
+            })
+        );
+    }
+
+    #[test]
+    fn test_score_ids() {
+        let index = build_index();
+        let query = index.build_vector(&["drive", "car"], None).unwrap();
+
+        let res = index.score_ids(&query, &[2, 0, 7, 1]);
+        let ids: Vec<_> = res.iter().map(|i| i.0).collect();
+        assert_eq!(ids, vec![2, 0, 1]);
+        assert_eq!(res[0].1, 0.0);
+        assert_eq!(res[2].1, 0.0);
+
+        let expected = index.knn(&query, 1)[0];
+        assert_eq!(res[1], expected);
+    }
+
+    #[test]
+    fn test_build_vector_reporting() {
+        let index = build_index();
+
+        let (vec, unresolved) = index.build_vector_reporting(&["car", "bike"]);
+        assert_eq!(vec, index.build_vector(&["car"], None));
+        assert_eq!(unresolved, vec!["bike".to_string()]);
+
+        let (vec, unresolved) = index.build_vector_reporting(&["bike"]);
+        assert!(vec.is_none());
+        assert_eq!(unresolved.len(), 1);
+    }
+
+    #[test]
+    fn test_knn_single_dim() {
+        let mut builder = IndexBuilder::new().with_weight(crate::build::weights::TFIDF);
+        for (pos, terms) in DOCUMENTS.iter().enumerate() {
+            builder.insert_raw_tf_vec(pos as u32, terms);
+        }
+        let index = builder.build(DefaultMetadata::default()).unwrap();
+
+        for term in ["a", "car", "stand"].iter() {
+            let query = index.build_vector_weights(&[(term, 2.5)]).unwrap();
+            let fast = index.knn(&query, 3);
+            let general = top_k(index.score_candidates(&query), 3);
+
+            assert_eq!(fast.len(), general.len());
+            for ((fast_id, fast_sim), (id, sim)) in fast.into_iter().zip(general) {
+                assert_eq!(fast_id, id);
+                assert!((fast_sim - sim).abs() < 0.0001);
+            }
+        }
+
+        // Documents with a zero length must not yield NaN
+        let mut builder = IndexBuilder::new();
+        builder.insert_new_weighted_vec(0u32, &[("a", 1.0), ("b", 0.5)]);
+        builder.insert_new_weighted_vec(1u32, &[("a", 0.0), ("c", 0.0)]);
+        let index = builder.build(DefaultMetadata::default()).unwrap();
+
+        let query = index.build_vector_weights(&[("a", 1.0)]).unwrap();
+        let res = index.knn(&query, 2);
+        assert_eq!(res.len(), 2);
+        assert!(res.iter().all(|(_, sim)| !sim.is_nan()));
+        assert_eq!(res[1], (1, 0.0));
+    }
+
+    #[test]
+    fn test_reweight() {
+        use crate::build::weights::{NoWeight, TFIDF};
+
+        let build = |weighted: bool, term_freqs: bool| {
+            let mut builder = IndexBuilder::new().with_term_freqs(term_freqs);
+            builder = if weighted {
+                builder.with_weight(TFIDF)
+            } else {
+                builder.with_weight(NoWeight)
+            };
+            for (pos, terms) in DOCUMENTS.iter().enumerate() {
+                builder.insert_new_vec(pos as u32, terms);
+            }
+            builder.build(DefaultMetadata::default()).unwrap()
+        };
+
+        // Term frequencies are required
+        let mut index = build(false, false);
+        assert!(matches!(
+            index.reweight(&TFIDF),
+            Err(Error::MissingTermFreqs)
+        ));
+
+        let mut index = build(false, true);
+        assert_eq!(
+            index.get_vector_store().term_freqs(2),
+            Some(&[(0, 2), (5, 1), (6, 1), (7, 1)][..])
+        );
+
+        // Term frequencies survive writing the index
+        let mut out = vec![];
+        index.write_to(&mut out).unwrap();
+        let read = Index::<u32, DefaultMetadata>::read_from(out.as_slice()).unwrap();
+        assert_eq!(
+            read.get_vector_store().term_freqs,
+            index.get_vector_store().term_freqs
+        );
+
+        index.reweight(&TFIDF).unwrap();
+        let expected = build(true, false);
+
+        let vectors = |index: &Index<u32, DefaultMetadata>| -> Vec<_> {
+            index
+                .get_vector_store()
+                .iter()
+                .map(|i| i.vector().clone())
+                .collect()
+        };
+        for (vec, expected) in vectors(&index).into_iter().zip(vectors(&expected)) {
+            assert_eq!(vec.dimen_count(), expected.dimen_count());
+            for ((dim, val), (exp_dim, exp_val)) in vec.iter().zip(expected.iter()) {
+                assert_eq!(dim, exp_dim);
+                assert!((val - exp_val).abs() < 0.0001);
+            }
+            assert!((vec.get_length() - expected.get_length()).abs() < 0.0001);
+        }
+        assert_eq!(
+            index.get_vector_store().lengths,
+            expected.get_vector_store().lengths
+        );
+    }
+
+    #[test]
+    fn test_stopwords_percentile() {
+        let index = build_index();
+
+        let all = index.stopwords_percentile(50.0);
+        assert_eq!(all.len(), index.get_indexer().len());
+
+        let common = index.stopwords_percentile(90.0);
+        assert_eq!(common, vec!["a".to_string(), "to".to_string()]);
+        assert!(common.len() < all.len());
+
+        assert_eq!(index.stopwords_percentile(0.0).len(), all.len());
+        assert_eq!(index.stopwords_percentile(100.0), common);
+    }
+
+    #[test]
+    fn test_read_section_order() {
+        let index = build_index();
+        let mut out = vec![];
+        index.write_to(&mut out).unwrap();
+
+        // Split into the header and the sections
+        let (header, mut rest) = out.split_at(5);
+        let mut sections = vec![];
+        while !rest.is_empty() {
+            let len = LittleEndian::read_u64(&rest[1..9]) as usize;
+            let (section, r) = rest.split_at(9 + len);
+            sections.push(section.to_vec());
+            rest = r;
+        }
+        assert_eq!(sections.len(), 6);
+
+        // Reorder sections and add an unknown one
+        sections.reverse();
+        let mut unknown = vec![200u8];
+        unknown.extend(3u64.to_le_bytes());
+        unknown.extend([1, 2, 3]);
+        sections.insert(2, unknown);
+
+        let reordered: Vec<u8> = header.iter().copied().chain(sections.concat()).collect();
+        let read = Index::<u32, DefaultMetadata>::read_from(reordered.as_slice()).unwrap();
+        assert_same_index(&index, &read);
+    }
+
+    #[test]
+    fn test_read_version_1() {
+        let index = build_index();
+        let mut out = vec![];
+        index.write_to(&mut out).unwrap();
+
+        // Version 1 stores the terms without front coding
+        let terms = index.indexer.encode::<LittleEndian>().unwrap();
+        let len = LittleEndian::read_u64(&out[6..14]) as usize;
+        let terms_start = 14 + len;
+        assert_eq!(out[terms_start], SECTION_TERMS);
+        let terms_len = LittleEndian::read_u64(&out[terms_start + 1..terms_start + 9]) as usize;
+
+        let mut v1 = out[..terms_start].to_vec();
+        v1[4] = 1;
+        write_section(&mut v1, SECTION_TERMS, &terms).unwrap();
+        v1.extend(&out[terms_start + 9 + terms_len..]);
+
+        let read = Index::<u32, DefaultMetadata>::read_from(v1.as_slice()).unwrap();
+        assert_same_index(&index, &read);
+
+        v1[4] = FORMAT_VERSION + 1;
+        assert!(Index::<u32, DefaultMetadata>::read_from(v1.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_build_tf_vector() {
+        let index = build_index();
+        let vec = index
+            .build_tf_vector(&["car", "a", "car", "unknown", "car"])
+            .unwrap();
+
+        let car = index.dim_for_term("car").unwrap() as u32;
+        let a = index.dim_for_term("a").unwrap() as u32;
+        assert_eq!(vec.dimen_count(), 2);
+        assert_eq!(vec.get_dim(car), Some(3.0));
+        assert_eq!(vec.get_dim(a), Some(1.0));
+        assert_eq!(vec.get_length(), 10.0f32.sqrt());
+
+        assert!(index.build_tf_vector(&["unknown"]).is_none());
+    }
+
+    #[test]
+    fn test_knn() {
+        let index = build_index();
+        let query = index.build_vector(&["make", "stand", "a"], None).unwrap();
+
+        let res = index.knn(&query, 2);
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].0, 2);
+        assert!(res[0].1 >= res[1].1);
+
+        let above = index.search_above(&query, res[0].1);
+        assert_eq!(above, vec![res[0]]);
+    }
+
+    #[test]
+    fn test_search_empty() {
+        let empty = Index::<u32, DefaultMetadata>::default();
+        let query = Vector::create_new_raw(vec![(0, 1.0)]);
+        assert!(empty.knn(&query, 10).is_empty());
+        assert!(empty.search_above(&query, 0.0).is_empty());
+
+        let index = build_index();
+        let empty_query = Vector::new_empty();
+        assert!(index.knn(&empty_query, 10).is_empty());
+        assert!(index.search_above(&empty_query, 0.0).is_empty());
+
+        let doc = index.get_vector_store().load_vector(0).unwrap();
+        assert!(!doc.vector().overlaps_with(&empty_query));
+        assert!(!empty_query.overlaps_with(doc.vector()));
+    }
+
+    #[test]
+    fn test_total_documents() {
+        let mut index = build_index();
+        assert_eq!(index.get_indexer().total_documents(), DOCUMENTS.len());
+
+        let car = index.dim_for_term("car").unwrap() as u32;
+        let idf = index.idf(car);
+        assert_eq!(idf, 3.0f32.log10());
+
+        index.get_indexer_mut().set_total_documents(30);
+        assert!(index.idf(car) > idf);
+        assert_eq!(index.idf(car), 30.0f32.log10());
+    }
+
+    #[test]
+    fn test_search() {
+        let index = build_index();
+        let query = index.build_vector(&["make", "stand", "a"], None).unwrap();
+
+        let all = index.knn(&query, 3);
+        assert_eq!(index.search(&query, 3, f32::MIN), all);
+        assert_eq!(index.search(&query, 1, f32::MIN), index.knn(&query, 1));
+
+        // Threshold trims the result below `k`
+        let res = index.search(&query, 3, all[0].1);
+        assert_eq!(res, vec![all[0]]);
+        assert!(index.search(&query, 0, 0.0).is_empty());
+    }
+
+    #[test]
+    fn test_match_counts() {
+        let index = build_index();
+        let query = index.build_vector(&["make", "stand", "a"], None).unwrap();
+        let full = query.vec_indices().count();
+
+        let counts = index.match_counts(&query, &[0, 2, 10]);
+        assert_eq!(counts, vec![(0, 1), (2, full)]);
+    }
+
+    #[cfg(feature = "genbktree")]
+    #[test]
+    fn test_build_vector_fuzzy() {
+        let index = build_index();
+        let stand = index.dim_for_term("stand").unwrap() as u32;
+        let car = index.dim_for_term("car").unwrap() as u32;
+
+        let vec = index
+            .build_vector_fuzzy(&["stnad", "car", "zzzzzz"], 2, 0.5)
+            .unwrap();
+        let dims: Vec<_> = vec.iter().collect();
+        let mut expected = vec![(stand, 0.5), (car, 1.0)];
+        expected.sort_by_key(|i| i.0);
+        assert_eq!(dims, expected);
+
+        assert!(index.build_vector_fuzzy(&["zzzzzz"], 2, 0.5).is_none());
+
+        // A term and its misspelling share the dimension of the exact match
+        let vec = index
+            .build_vector_fuzzy(&["stnad", "stand"], 2, 0.5)
+            .unwrap();
+        assert_eq!(vec.sparse_vec(), &vec![(stand, 1.0)]);
+        assert_eq!(vec.get_length(), 1.0);
+    }
+
+    #[test]
+    fn test_diff() {
+        let index = build_index();
+        assert!(index.diff(&index).is_empty());
+        assert!(index.diff(&index.clone_heavy()).is_empty());
+
+        let mut modified = index.clone_heavy();
+        {
+            let mut vec = modified.get_vector_store_mut().mod_vector(1).unwrap();
+            vec.sparse_iter_mut().next().unwrap().1 *= 2.0;
+        }
+        assert_eq!(index.diff(&modified).changed_documents, vec![1]);
+
+        // Replace a term occurring in a single document only so all other weights stay the same
+        let mut builder = IndexBuilder::new();
+        builder.insert_new_vec(0, DOCUMENTS[0]);
+        builder.insert_new_vec(1, DOCUMENTS[1]);
+        builder.insert_new_vec(2, &["to", "make", "a", "new", "a"]);
+        let other: Index<u32, DefaultMetadata> = builder.build(DefaultMetadata::default()).unwrap();
+
+        let diff = index.diff(&other);
+        assert_eq!(diff.terms_only_in_self, vec!["stand"]);
+        assert_eq!(diff.terms_only_in_other, vec!["new"]);
+        assert_eq!(diff.changed_documents, vec![2]);
+    }
+
+    #[test]
+    fn test_knn_overlap() {
+        let index = build_index();
+        let query = index.build_vector(&["make", "stand"], None).unwrap();
+
+        let res = index.knn_overlap(&query, 3);
+        assert_eq!(res, vec![(2, 1.0)]);
+        assert!(index.knn_overlap(&query, 0).is_empty());
+    }
+
+    #[test]
+    fn test_build_vector_boosted() {
+        let index = build_index();
+
+        let query = index
+            .build_vector_boosted(&[("car", 1.0), ("call", 2.0)])
+            .unwrap();
+        assert_eq!(index.knn(&query, 1)[0].0, 1);
+
+        let query = index
+            .build_vector_boosted(&[("car", 2.0), ("call", 1.0)])
+            .unwrap();
+        assert_eq!(index.knn(&query, 1)[0].0, 0);
+
+        assert!(index.build_vector_boosted(&[("unknown", 1.0)]).is_none());
+    }
+
+    #[test]
+    fn test_merge_dimensions() {
+        let mut index = build_index();
+        let car = index.dim_for_term("car").unwrap() as u32;
+        let call = index.dim_for_term("call").unwrap() as u32;
+        let drive = index.dim_for_term("drive").unwrap() as u32;
+        let canonical = car.min(call);
+
+        index.merge_dimensions(&[vec![car, call]]).unwrap();
+
+        let store = index.get_vector_store();
+        assert_eq!(store.get_in_dims(&[canonical]), vec![0, 1]);
+        assert_eq!(store.dimension_size(car.max(call)), 0);
+
+        let doc0 = store.load_vector(0).unwrap();
+        let doc1 = store.load_vector(1).unwrap();
+        assert!(doc0.vector().has_dim(canonical));
+        assert!(doc1.vector().has_dim(canonical));
+        assert!(doc0.vector().has_dim(drive));
+
+        // Term frequencies of merged dimensions get summed up
+        let mut builder = IndexBuilder::new().with_term_freqs(true);
+        for (pos, terms) in DOCUMENTS.iter().enumerate() {
+            builder.insert_new_vec(pos as u32, terms);
+        }
+        let mut index = builder.build(DefaultMetadata::default()).unwrap();
+        let a = index.dim_for_term("a").unwrap() as u32;
+        let to = index.dim_for_term("to").unwrap() as u32;
+        let stand = index.dim_for_term("stand").unwrap() as u32;
+
+        index.merge_dimensions(&[vec![a, to]]).unwrap();
+        let term_freqs = index.get_vector_store().term_freqs(2).unwrap();
+        assert_eq!(term_freqs.len(), 3);
+        assert!(term_freqs.contains(&(a.min(to), 3)));
+        assert!(term_freqs.contains(&(stand, 1)));
+    }
+
+    #[test]
+    fn test_keep_gap_threshold() {
+        let mut builder = IndexBuilder::new()
+            .with_gap_threshold(0)
+            .with_term_freqs(true);
+        for (pos, terms) in DOCUMENTS.iter().enumerate() {
+            builder.insert_raw_tf_vec(pos as u32, terms);
+        }
+        let mut index = builder.build(DefaultMetadata::default()).unwrap();
+        let threshold = |index: &Index<u32, DefaultMetadata>| {
+            index.get_vector_store().get_map().gap_threshold()
+        };
+        assert_eq!(threshold(&index), 0);
+
+        index.merge_dimensions(&[vec![0, 1]]).unwrap();
+        assert_eq!(threshold(&index), 0);
+        index.reweight(&crate::build::weights::TFIDF).unwrap();
+        assert_eq!(threshold(&index), 0);
+        index.get_vector_store_mut().rebuild_index();
+        assert_eq!(threshold(&index), 0);
+        index.retain_documents(|doc| doc.document != 1).unwrap();
+        assert_eq!(threshold(&index), 0);
+
+        let mut out = vec![];
+        index.write_to(&mut out).unwrap();
+        let read = Index::<u32, DefaultMetadata>::read_from(out.as_slice()).unwrap();
+        assert_eq!(threshold(&read), 0);
+    }
+
+    #[test]
+    fn test_search_terms_explained() {
+        let index = build_index();
+        let query = index.build_vector(&["make", "stand", "car"], None).unwrap();
+
+        let res = index.search_terms_explained(&["make", "stand", "car"], 2);
+        assert_eq!(res.len(), 2);
+
+        let (id, score, mut matched) = res[0].clone();
+        matched.sort();
+        assert_eq!(id, 2);
+        assert_eq!(matched, vec!["make", "stand"]);
+        assert_eq!(score, index.knn(&query, 1)[0].1);
+
+        assert_eq!(res[1].0, 0);
+        assert_eq!(res[1].2, vec!["car"]);
+
+        assert!(index.search_terms_explained(&["unknown"], 2).is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_reads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Index<u32, DefaultMetadata>>();
+
+        let index = std::sync::Arc::new(build_index());
+        let queries = [vec!["make", "stand"], vec!["car"], vec!["to", "call"]];
+        let expected: Vec<_> = queries
+            .iter()
+            .map(|q| index.search_terms_explained(q, 3))
+            .collect();
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let index = index.clone();
+                let queries = queries.clone();
+                std::thread::spawn(move || {
+                    (0..50)
+                        .map(|i| {
+                            let query = &queries[i % queries.len()];
+                            index.search_terms_explained(query, 3)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            for (i, res) in handle.join().unwrap().into_iter().enumerate() {
+                assert_eq!(res, expected[i % queries.len()]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_masked() {
+        let index = build_index();
+        let car = index.dim_for_term("car").unwrap() as u32;
+        let stand = index.dim_for_term("stand").unwrap() as u32;
+        let query = index.build_vector(&["car", "stand"], None).unwrap();
+
+        let ids = |res: Vec<(u32, f32)>| -> Vec<u32> { res.into_iter().map(|i| i.0).collect() };
+
+        let mut all = ids(index.search_masked(&query, None, None, 3));
+        all.sort_unstable();
+        assert_eq!(all, vec![0, 2]);
+
+        let deny: HashSet<_> = [car].iter().copied().collect();
+        assert_eq!(
+            ids(index.search_masked(&query, None, Some(&deny), 3)),
+            vec![2]
+        );
+
+        let allow: HashSet<_> = [car].iter().copied().collect();
+        assert_eq!(
+            ids(index.search_masked(&query, Some(&allow), None, 3)),
+            vec![0]
+        );
+
+        let deny: HashSet<_> = [car, stand].iter().copied().collect();
+        assert!(index.search_masked(&query, None, Some(&deny), 3).is_empty());
+    }
+
+    #[test]
+    fn test_add_doc_frequency() {
+        let mut index = build_index();
+        let car = index.dim_for_term("car").unwrap();
+        let idf = index.idf(car as u32);
+
+        // Account for two more documents containing "car"
+        let indexer = index.get_indexer_mut();
+        assert!(indexer.add_doc_frequency(car, 2));
+        indexer.set_total_documents(DOCUMENTS.len() + 2);
+
+        assert_eq!(
+            index.get_indexer().load_term(car).unwrap().doc_frequency(),
+            3
+        );
+        assert_eq!(index.get_indexer().get_term("car"), Some(car));
+        assert!(index.idf(car as u32) < idf);
+
+        assert!(!index.get_indexer_mut().add_doc_frequency(1000, 1));
+    }
+
+    #[test]
+    fn test_more_like() {
+        let mut builder = IndexBuilder::new();
+        for (pos, terms) in DOCUMENTS.iter().enumerate() {
+            builder.insert_new_vec(pos as u32, terms);
+        }
+        // Near duplicate of the first document
+        builder.insert_new_vec(3, &["to", "drive", "a", "red", "car"]);
+        let index: Index<u32, DefaultMetadata> = builder.build(DefaultMetadata::default()).unwrap();
+
+        let res = index.more_like(0, 2);
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].0, 3);
+        assert!(res.iter().all(|(id, _)| *id != 0));
+
+        assert!(index.more_like(100, 2).is_empty());
+    }
+}
@@ -1,8 +1,16 @@
-use crate::{error::Error, traits::Encodable};
+use crate::{
+    error::Error,
+    traits::{Decodable, Encodable, MAX_PREALLOC},
+};
+use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt};
 use compressed_vec::{buffered::BufCVecRef, CVec};
 use indexed_file::{any::CloneableIndexedReader, index::Index, IndexableFile};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{ErrorKind, Read, Write},
+    sync::Arc,
+};
 
 /// A Dimension Vector map maps a dimension to all references of vectors which lay in the
 /// dimension. This allows much more efficient searching
@@ -12,14 +20,33 @@ pub struct InvertedIndex {
     index: Index,
     /// Contains the vector ids for each dimension
     data: CVec,
+    /// First dimension and its position in `index` of each run of dimensions following a gap
+    /// larger than the builders gap threshold. Dimensions within a run are padded, so a run only
+    /// needs a single entry. The first run, starting at dimension 0, isn't stored
+    runs: Vec<(u32, u32)>,
+    /// The gap threshold the index was built with. Used when it gets rebuilt
+    gap_threshold: u32,
 }
 
 impl InvertedIndex {
     /// Returns a vec over all Vector IDs in dimension `dim`
+    #[inline]
     pub fn get(&self, dim: u32) -> Option<Vec<u32>> {
-        let arr_start = self.index.get2(dim as usize)? as usize;
+        let mut buf_vec = BufCVecRef::new(&self.data);
+        self.get_buffered(&mut buf_vec, dim)
+    }
 
+    /// Returns the Vector IDs of all given dimensions. Dimensions without any vector are left out
+    pub fn get_multi(&self, dims: &[u32]) -> Vec<(u32, Vec<u32>)> {
         let mut buf_vec = BufCVecRef::new(&self.data);
+        dims.iter()
+            .filter_map(|dim| Some((*dim, self.get_buffered(&mut buf_vec, *dim)?)))
+            .collect()
+    }
+
+    /// Returns a vec over all Vector IDs in dimension `dim` using the given buffered `data`
+    fn get_buffered(&self, buf_vec: &mut BufCVecRef, dim: u32) -> Option<Vec<u32>> {
+        let arr_start = self.index.get2(self.index_pos(dim)?)? as usize;
 
         // Length of following vec containing the vector IDs
         let arr_len = *buf_vec.get_buffered(arr_start)? as usize;
@@ -39,6 +66,57 @@ impl InvertedIndex {
         Some(out)
     }
 
+    /// Returns the position in `index` of the dimension `dim` or `None` if it's not part of any
+    /// run
+    #[inline]
+    fn index_pos(&self, dim: u32) -> Option<usize> {
+        if self.runs.is_empty() {
+            return Some(dim as usize);
+        }
+
+        let run = self.runs.partition_point(|i| i.0 <= dim);
+        let (start_dim, start_pos) = match run {
+            0 => (0, 0),
+            _ => self.runs[run - 1],
+        };
+        let end_pos = self.run_end(run);
+
+        let pos = start_pos as usize + (dim - start_dim) as usize;
+        (pos < end_pos).then_some(pos)
+    }
+
+    /// Returns the end position in `index` of the run `run`. Run 0 is the one starting at
+    /// dimension 0
+    #[inline]
+    fn run_end(&self, run: usize) -> usize {
+        self.runs
+            .get(run)
+            .map(|i| i.1 as usize)
+            .unwrap_or_else(|| self.index.len())
+    }
+
+    /// Returns an iterator over all dimensions having an entry in `data`, including padded ones
+    fn dims(&self) -> impl Iterator<Item = u32> + '_ {
+        let runs = std::iter::once((0, 0)).chain(self.runs.iter().copied());
+        runs.enumerate()
+            .flat_map(move |(run, (start_dim, start_pos))| {
+                let len = self.run_end(run) - start_pos as usize;
+                start_dim..start_dim + len as u32
+            })
+    }
+
+    /// Returns the amount of dimensions having an entry in `data`, including padded ones
+    #[inline]
+    fn dim_entries(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns the largest gap between two dimensions which gets filled with padding
+    #[inline]
+    pub fn gap_threshold(&self) -> u32 {
+        self.gap_threshold
+    }
+
     /// Returns true if there is at least one vector in dimension `dim`
     #[inline]
     pub fn has(&self, dim: u32) -> bool {
@@ -46,73 +124,150 @@ impl InvertedIndex {
     }
 
     pub fn decoded_map(&self) -> DimToVecs {
-        let mut map = HashMap::<u32, Vec<u32>>::with_capacity(self.index.len());
+        let mut map = HashMap::<u32, Vec<u32>>::with_capacity(self.dim_entries());
+        map.extend(self.iter_postings());
+        map
+    }
+
+    /// Like `decoded_map` but returns the dimensions in ascending order. Dimensions without any
+    /// vector are left out
+    pub fn decoded_btree(&self) -> BTreeMap<u32, Vec<u32>> {
+        self.iter_postings().collect()
+    }
+
+    /// Returns an iterator over all dimensions with at least one vector and their vector IDs in
+    /// ascending order of the dimensions
+    pub fn iter_postings(&self) -> impl Iterator<Item = (u32, Vec<u32>)> + '_ {
+        let mut buf_vec = BufCVecRef::new(&self.data);
+        self.dims()
+            .filter_map(move |dim| Some((dim, self.get_buffered(&mut buf_vec, dim)?)))
+    }
 
-        for dim in 0..self.index.len() {
-            let get = self.get(dim as u32).unwrap();
-            map.insert(dim as u32, get);
+    /// Writes all dimensions with at least one vector in a simple text format into `w`. Each
+    /// dimension is written in its own line followed by a tab and its comma separated vector IDs,
+    /// eg. `3\t0,4,7`
+    pub fn write_postings<W: Write>(&self, mut w: W) -> Result<(), Error> {
+        for (dim, vecs) in self.iter_postings() {
+            if vecs.is_empty() {
+                continue;
+            }
+
+            write!(w, "{}\t", dim)?;
+            for (pos, id) in vecs.iter().enumerate() {
+                if pos > 0 {
+                    w.write_all(b",")?;
+                }
+                write!(w, "{}", id)?;
+            }
+            w.write_all(b"\n")?;
         }
 
-        map
+        Ok(())
+    }
+
+    /// Returns statistics about the compression of the stored vector IDs
+    pub fn compression_stats(&self) -> CompressionStats {
+        // Each dimension has one additional item in `data` holding the length of its vector IDs
+        let element_count = self.data.len() - self.dim_entries();
+        let byte_len = self.data.byte_len();
+
+        let bytes_per_element = if element_count > 0 {
+            byte_len as f32 / element_count as f32
+        } else {
+            0.0
+        };
+
+        CompressionStats {
+            element_count,
+            byte_len,
+            bytes_per_element,
+        }
     }
 
     pub fn byte_len(&self) -> usize {
-        self.index.len_bytes() + self.data.byte_len()
+        self.index.len_bytes() + self.data.byte_len() + self.runs.len() * 8
     }
 }
 
+/// Compression statistics of an `InvertedIndex`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressionStats {
+    /// Amount of stored vector IDs over all dimensions
+    pub element_count: usize,
+    /// Size of the compressed vector IDs in bytes
+    pub byte_len: usize,
+    /// Average amount of bytes used for a single vector ID
+    pub bytes_per_element: f32,
+}
+
 pub type DimToVecs = HashMap<u32, Vec<u32>>;
 
+/// Default size of the largest gap between two dimensions which gets filled with padding
+pub(crate) const DEFAULT_GAP_THRESHOLD: u32 = 64;
+
 #[derive(Debug, Clone)]
 pub(crate) struct NewDimVecMap {
     pub(crate) map: DimToVecs,
+    gap_threshold: u32,
 }
 
 impl NewDimVecMap {
     #[inline]
     pub(crate) fn new(map: DimToVecs) -> Self {
-        Self { map }
+        Self {
+            map,
+            gap_threshold: DEFAULT_GAP_THRESHOLD,
+        }
+    }
+
+    /// Sets the largest gap between two dimensions which gets filled with padding. Larger gaps
+    /// start a new run of dimensions instead, which is stored with its explicit position
+    #[inline]
+    pub(crate) fn with_gap_threshold(mut self, gap_threshold: u32) -> Self {
+        self.gap_threshold = gap_threshold;
+        self
     }
 
+    /// Builds the inverted index. Dimensions and their vectors get sorted first, so the result
+    /// doesn't depend on the iteration order of the map and equal input always results in the
+    /// same bytes
     pub fn build(self) -> InvertedIndex {
         // Index position for each vector
         let mut file_index = Vec::new();
 
-        let mut sorted_map = self
-            .map
-            .iter()
-            .map(|(k, v)| (*k, v.clone()))
-            .collect::<Vec<_>>();
-        sorted_map.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut sorted_map = self.map.into_iter().collect::<Vec<_>>();
+        sorted_map.sort_unstable_by_key(|i| i.0);
 
         let mut map_store = CVec::new();
-        let mut last_dim: Option<u32> = None;
+        let mut runs = Vec::new();
+        let mut next_dim = 0;
 
         for (dim, mut vecs) in sorted_map {
-            if last_dim.is_none() {
-                last_dim = Some(dim);
-            }
-
-            // Fill non mapped dimensions with 0s to make the CVS replace a HashMap
-            let ld = last_dim.as_ref().unwrap();
-            for _ in ld + 1..dim {
-                file_index.push(map_store.len() as u32);
-                map_store.push(0);
+            if dim - next_dim > self.gap_threshold {
+                // Start a new run instead of padding large gaps
+                runs.push((dim, file_index.len() as u32));
+            } else {
+                // Fill non mapped dimensions with 0s to make the CVS replace a HashMap
+                for _ in next_dim..dim {
+                    file_index.push(map_store.len() as u32);
+                    map_store.push(0);
+                }
             }
 
             vecs.sort_unstable();
             file_index.push(map_store.len() as u32);
-
             map_store.push(vecs.len() as u32);
             map_store.extend(vecs);
 
-            last_dim = Some(dim);
+            next_dim = dim + 1;
         }
 
         let index = Index::new(file_index).zero_len();
         InvertedIndex {
             index,
             data: map_store,
+            runs,
+            gap_threshold: self.gap_threshold,
         }
     }
 }
@@ -130,16 +285,11 @@ impl Encodable for NewDimVecMap {
         sorted_map.sort_by(|a, b| a.0.cmp(&b.0));
 
         let mut map_store = CVec::new();
-        let mut last_dim: Option<u32> = None;
+        let mut next_dim = 0;
 
         for (dim, mut vecs) in sorted_map {
-            if last_dim.is_none() {
-                last_dim = Some(dim);
-            }
-
             // Fill non mapped dimensions with 0s to make the CVS replace a HashMap
-            let ld = last_dim.as_ref().unwrap();
-            for _ in ld + 1..dim {
+            for _ in next_dim..dim {
                 file_index.push(map_store.len() as u32);
                 map_store.push(0);
             }
@@ -150,7 +300,7 @@ impl Encodable for NewDimVecMap {
             map_store.push(vecs.len() as u32);
             map_store.extend(vecs);
 
-            last_dim = Some(dim);
+            next_dim = dim + 1;
         }
 
         let index = Arc::new(Index::new(file_index).zero_len());
@@ -165,12 +315,236 @@ impl Encodable for NewDimVecMap {
     }
 }
 
+impl Encodable for InvertedIndex {
+    fn encode<T: ByteOrder>(&self) -> Result<Vec<u8>, Error> {
+        let dims = self.decoded_btree();
+
+        let mut out = vec![];
+        out.write_u32::<T>(dims.len() as u32)?;
+
+        for (dim, vecs) in dims {
+            out.write_u32::<T>(dim)?;
+            out.extend(vecs.encode::<T>()?);
+        }
+
+        out.write_u32::<T>(self.gap_threshold)?;
+
+        Ok(out)
+    }
+}
+
+impl Decodable for InvertedIndex {
+    fn decode<T: ByteOrder, R: Read>(mut data: R) -> Result<Self, Error> {
+        let len = data.read_u32::<T>()? as usize;
+        let mut map = DimToVecs::with_capacity(len.min(MAX_PREALLOC));
+
+        for _ in 0..len {
+            let dim = data.read_u32::<T>()?;
+            let vecs = Vec::<u32>::decode::<T, _>(&mut data)?;
+            map.insert(dim, vecs);
+        }
+
+        // Older indexes don't store the gap threshold
+        let gap_threshold = match data.read_u32::<T>() {
+            Ok(gap_threshold) => gap_threshold,
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => DEFAULT_GAP_THRESHOLD,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(NewDimVecMap::new(map)
+            .with_gap_threshold(gap_threshold)
+            .build())
+    }
+}
+
+/// Layout of an `InvertedIndex` serialized before dimensions following large gaps were stored
+/// without padding
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub(crate) struct LegacyInvertedIndex {
+    index: Index,
+    data: CVec,
+}
+
+#[cfg(test)]
+impl From<&InvertedIndex> for LegacyInvertedIndex {
+    fn from(inv_index: &InvertedIndex) -> Self {
+        assert!(inv_index.runs.is_empty());
+        Self {
+            index: inv_index.index.clone(),
+            data: inv_index.data.clone(),
+        }
+    }
+}
+
+impl From<LegacyInvertedIndex> for InvertedIndex {
+    #[inline]
+    fn from(legacy: LegacyInvertedIndex) -> Self {
+        Self {
+            index: legacy.index,
+            data: legacy.data,
+            runs: Vec::new(),
+            gap_threshold: DEFAULT_GAP_THRESHOLD,
+        }
+    }
+}
+
 impl Default for InvertedIndex {
     #[inline]
     fn default() -> Self {
         Self {
             index: Default::default(),
             data: CVec::new(),
+            runs: Vec::new(),
+            gap_threshold: DEFAULT_GAP_THRESHOLD,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use byteorder::LittleEndian;
+
+    fn test_map() -> DimToVecs {
+        let mut map = DimToVecs::new();
+        map.insert(0, vec![1, 2]);
+        map.insert(3, vec![0]);
+        map.insert(7, vec![2, 0, 1]);
+        map
+    }
+
+    #[test]
+    fn test_decoded_btree() {
+        let inv_index = NewDimVecMap::new(test_map()).build();
+
+        let first: Vec<_> = inv_index.decoded_btree().into_iter().collect();
+        let second: Vec<_> = inv_index.decoded_btree().into_iter().collect();
+        assert_eq!(first, second);
+        assert_eq!(
+            first,
+            vec![(0, vec![1, 2]), (3, vec![0]), (7, vec![0, 1, 2])]
+        );
+    }
+
+    #[test]
+    fn test_compression_stats() {
+        let map = test_map();
+        let inv_index = NewDimVecMap::new(map.clone()).build();
+
+        let stats = inv_index.compression_stats();
+        let posting_count: usize = map.values().map(|i| i.len()).sum();
+        assert_eq!(stats.element_count, posting_count);
+        assert_eq!(stats.byte_len, inv_index.data.byte_len());
+    }
+
+    #[test]
+    fn test_get_multi() {
+        let inv_index = NewDimVecMap::new(test_map()).build();
+        let dims = [7, 1, 0, 3, 100];
+
+        let single: Vec<_> = dims
+            .iter()
+            .filter_map(|dim| Some((*dim, inv_index.get(*dim)?)))
+            .collect();
+        assert_eq!(inv_index.get_multi(&dims), single);
+        assert_eq!(single.len(), 3);
+    }
+
+    #[test]
+    fn test_iter_postings() {
+        let map = test_map();
+        let inv_index = NewDimVecMap::new(map.clone()).build();
+
+        let dims: Vec<_> = inv_index.iter_postings().map(|i| i.0).collect();
+        let mut expected: Vec<_> = map.keys().copied().collect();
+        expected.sort_unstable();
+        assert_eq!(dims, expected);
+
+        for (dim, vecs) in inv_index.iter_postings() {
+            assert_eq!(Some(vecs), inv_index.get(dim));
         }
     }
+
+    #[test]
+    fn test_write_postings() {
+        let mut map = test_map();
+        map.insert(500, vec![4]);
+        let inv_index = NewDimVecMap::new(map).build();
+
+        let mut out = vec![];
+        inv_index.write_postings(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text, "0\t1,2\n3\t0\n7\t0,1,2\n500\t4\n");
+
+        let parsed: BTreeMap<u32, Vec<u32>> = text
+            .lines()
+            .map(|line| {
+                let (dim, ids) = line.split_once('\t').unwrap();
+                let ids = ids.split(',').map(|id| id.parse().unwrap()).collect();
+                (dim.parse().unwrap(), ids)
+            })
+            .collect();
+        assert_eq!(parsed, inv_index.decoded_btree());
+    }
+
+    #[test]
+    fn test_gap_threshold() {
+        let mut map = DimToVecs::new();
+        map.insert(0, vec![1, 2]);
+        map.insert(2, vec![0]);
+        map.insert(1000, vec![3]);
+        map.insert(1010, vec![1]);
+        map.insert(5000, vec![0, 4]);
+
+        let sparse = NewDimVecMap::new(map.clone()).build();
+        let padded = NewDimVecMap::new(map.clone())
+            .with_gap_threshold(u32::MAX)
+            .build();
+        // Dimensions 1000 and 1010 share a run, so only the gap in between gets padded
+        assert_eq!(sparse.runs, vec![(1000, 3), (5000, 14)]);
+        assert_eq!(sparse.dim_entries(), 15);
+        assert!(padded.runs.is_empty());
+        assert!(sparse.byte_len() < padded.byte_len());
+
+        assert_eq!(
+            sparse.iter_postings().collect::<Vec<_>>(),
+            padded.iter_postings().collect::<Vec<_>>()
+        );
+        for dim in [0, 1, 2, 3, 999, 1000, 1005, 1010, 1011, 5000, 5001] {
+            assert_eq!(sparse.get(dim), padded.get(dim));
+            assert_eq!(sparse.get(dim), map.get(&dim).cloned());
+        }
+
+        // Indexes starting with a large gap have an empty first run
+        let mut map = DimToVecs::new();
+        map.insert(100, vec![1]);
+        map.insert(101, vec![2]);
+        let inv_index = NewDimVecMap::new(map.clone()).build();
+        assert_eq!(inv_index.runs, vec![(100, 0)]);
+        assert_eq!(inv_index.get(0), None);
+        assert_eq!(inv_index.get(100), Some(vec![1]));
+        assert_eq!(inv_index.get(102), None);
+        assert_eq!(inv_index.decoded_map(), map);
+        assert_eq!(
+            sparse.compression_stats().element_count,
+            padded.compression_stats().element_count
+        );
+
+        // The threshold is kept when encoding
+        let enc = padded.encode::<LittleEndian>().unwrap();
+        let dec = InvertedIndex::decode::<LittleEndian, _>(enc.as_slice()).unwrap();
+        assert_eq!(dec.gap_threshold(), u32::MAX);
+        assert!(dec.runs.is_empty());
+
+        // Indexes encoded without a threshold use the default one
+        let dec = InvertedIndex::decode::<LittleEndian, _>(&enc[..enc.len() - 4]).unwrap();
+        assert_eq!(dec.gap_threshold(), DEFAULT_GAP_THRESHOLD);
+        assert_eq!(dec.runs.len(), 2);
+
+        // A corrupted dimension count must not be used for allocating
+        let mut corrupted = enc;
+        corrupted[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(InvertedIndex::decode::<LittleEndian, _>(corrupted.as_slice()).is_err());
+    }
 }
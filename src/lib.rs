@@ -5,6 +5,7 @@ pub mod index;
 pub mod inv_index;
 pub mod lock_step;
 pub mod metadata;
+pub mod namespace;
 pub mod term_store;
 pub mod traits;
 pub mod vector;
@@ -53,3 +53,63 @@ where
         }
     }
 }
+
+/// Like `LockStepIter` but yields every key of both iterators (full outer join). Keys present in
+/// only one of both iterators have `None` as value for the other one.
+pub(crate) struct LockStepOuterIter<A, B, K, V, W>
+where
+    A: Iterator<Item = (K, V)>,
+    B: Iterator<Item = (K, W)>,
+{
+    a: Peekable<A>,
+    b: Peekable<B>,
+}
+
+impl<A, B, K, V, W> LockStepOuterIter<A, B, K, V, W>
+where
+    A: Iterator<Item = (K, V)>,
+    B: Iterator<Item = (K, W)>,
+{
+    #[inline]
+    pub fn new(a: A, b: B) -> Self {
+        Self {
+            a: a.peekable(),
+            b: b.peekable(),
+        }
+    }
+}
+
+impl<A, B, K, V, W> Iterator for LockStepOuterIter<A, B, K, V, W>
+where
+    A: Iterator<Item = (K, V)>,
+    B: Iterator<Item = (K, W)>,
+    K: Ord,
+{
+    type Item = (K, Option<V>, Option<W>);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let ord = match (self.a.peek(), self.b.peek()) {
+            (Some((dim_a, _)), Some((dim_b, _))) => dim_a.cmp(dim_b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return None,
+        };
+
+        match ord {
+            Ordering::Less => {
+                let (dim, value_a) = self.a.next()?;
+                Some((dim, Some(value_a), None))
+            }
+            Ordering::Greater => {
+                let (dim, value_b) = self.b.next()?;
+                Some((dim, None, Some(value_b)))
+            }
+            Ordering::Equal => {
+                let (dim, value_a) = self.a.next()?;
+                let (_, value_b) = self.b.next()?;
+                Some((dim, Some(value_a), Some(value_b)))
+            }
+        }
+    }
+}
@@ -3,9 +3,14 @@ use crate::{
     traits::{Decodable, Encodable},
 };
 use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{self, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    ser::SerializeStruct,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 use std::{
     convert::TryFrom,
+    fmt,
     io::{Cursor, Read},
 };
 
@@ -14,11 +19,17 @@ use std::{
 #[repr(u8)]
 pub enum IndexVersion {
     V1 = 0u8,
+    /// Stores the amount of terms of each document in the `VectorStore`
+    V2 = 1u8,
+    /// The inverted index stores dimensions following large gaps without padding
+    V3 = 2u8,
+    /// `DefaultMetadata` stores the average document length
+    V4 = 3u8,
 }
 
 impl Default for IndexVersion {
     fn default() -> Self {
-        Self::V1
+        Self::V4
     }
 }
 
@@ -29,6 +40,9 @@ impl TryFrom<u8> for IndexVersion {
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         Ok(match value {
             0 => Self::V1,
+            1 => Self::V2,
+            2 => Self::V3,
+            3 => Self::V4,
             _ => return Err(Error::Decode),
         })
     }
@@ -42,6 +56,17 @@ pub trait Metadata: Encodable + Decodable {
 
     fn set_document_count(&mut self, count: usize);
 
+    /// Returns the average amount of terms per document, eg. for BM25. Returns `0.0` if the
+    /// metadata doesn't store it
+    #[inline]
+    fn avg_doc_length(&self) -> f32 {
+        0.0
+    }
+
+    /// Sets the average amount of terms per document. Does nothing if the metadata doesn't store it
+    #[inline]
+    fn set_avg_doc_length(&mut self, _avg_doc_length: f32) {}
+
     /// Loads an existing Metadata file
     fn load<R: Read>(mut reader: R) -> Result<Self, Error> {
         let mut buf = vec![];
@@ -50,11 +75,15 @@ pub trait Metadata: Encodable + Decodable {
     }
 }
 
-/// Various metadata for the given Index
-#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+/// Various metadata for the given Index. Serializing it only includes the average document length
+/// if `version` is `IndexVersion::V4` or newer, so metadata of older indexes can still be
+/// deserialized
+#[derive(Debug, Clone, Copy, Default)]
 pub struct DefaultMetadata {
     pub version: IndexVersion,
     pub document_count: usize,
+    /// Only stored with `IndexVersion::V4` or newer
+    pub avg_doc_length: f32,
 }
 
 impl DefaultMetadata {
@@ -63,8 +92,15 @@ impl DefaultMetadata {
         Self {
             version,
             document_count: 0,
+            avg_doc_length: 0.0,
         }
     }
+
+    /// Returns `true` if the average document length gets stored
+    #[inline]
+    fn has_avg_doc_length(&self) -> bool {
+        self.version as u8 >= IndexVersion::V4 as u8
+    }
 }
 
 impl Metadata for DefaultMetadata {
@@ -79,6 +115,14 @@ impl Metadata for DefaultMetadata {
     fn set_document_count(&mut self, count: usize) {
         self.document_count = count;
     }
+
+    fn avg_doc_length(&self) -> f32 {
+        self.avg_doc_length
+    }
+
+    fn set_avg_doc_length(&mut self, avg_doc_length: f32) {
+        self.avg_doc_length = avg_doc_length;
+    }
 }
 
 impl Encodable for DefaultMetadata {
@@ -87,6 +131,9 @@ impl Encodable for DefaultMetadata {
 
         out.write_u8(self.version as u8)?;
         out.write_u64::<T>(self.document_count as u64)?;
+        if self.has_avg_doc_length() {
+            out.write_f32::<T>(self.avg_doc_length)?;
+        }
 
         Ok(out)
     }
@@ -96,10 +143,128 @@ impl Decodable for DefaultMetadata {
     fn decode<T: ByteOrder, R: Read>(mut data: R) -> Result<Self, Error> {
         let version = IndexVersion::try_from(data.read_u8()?)?;
         let document_count = data.read_u64::<T>()? as usize;
+        let mut avg_doc_length = 0.0;
+        if version as u8 >= IndexVersion::V4 as u8 {
+            avg_doc_length = data.read_f32::<T>()?;
+        }
 
         Ok(Self {
             version,
             document_count,
+            avg_doc_length,
         })
     }
 }
+
+impl Serialize for DefaultMetadata {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let has_avg_doc_length = self.has_avg_doc_length();
+        let len = if has_avg_doc_length { 3 } else { 2 };
+
+        let mut state = serializer.serialize_struct("DefaultMetadata", len)?;
+        state.serialize_field("version", &self.version)?;
+        state.serialize_field("document_count", &self.document_count)?;
+        if has_avg_doc_length {
+            state.serialize_field("avg_doc_length", &self.avg_doc_length)?;
+        } else {
+            state.skip_field("avg_doc_length")?;
+        }
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for DefaultMetadata {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        const FIELDS: &[&str] = &["version", "document_count", "avg_doc_length"];
+        deserializer.deserialize_struct("DefaultMetadata", FIELDS, MetadataVisitor)
+    }
+}
+
+struct MetadataVisitor;
+
+impl<'de> Visitor<'de> for MetadataVisitor {
+    type Value = DefaultMetadata;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("struct DefaultMetadata")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let version = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let document_count = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+
+        let mut metadata = DefaultMetadata {
+            document_count,
+            ..DefaultMetadata::new(version)
+        };
+
+        // Non self-describing formats like bincode don't have this field in older versions
+        if metadata.has_avg_doc_length() {
+            metadata.avg_doc_length = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        }
+
+        Ok(metadata)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut version = None;
+        let mut document_count = None;
+        let mut avg_doc_length = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "version" => version = Some(map.next_value()?),
+                "document_count" => document_count = Some(map.next_value()?),
+                "avg_doc_length" => avg_doc_length = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(DefaultMetadata {
+            version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+            document_count: document_count
+                .ok_or_else(|| de::Error::missing_field("document_count"))?,
+            avg_doc_length: avg_doc_length.unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_serde_versions() {
+        let v4 = DefaultMetadata {
+            document_count: 7,
+            avg_doc_length: 2.5,
+            ..DefaultMetadata::new(IndexVersion::V4)
+        };
+        let v1 = DefaultMetadata {
+            document_count: 7,
+            avg_doc_length: 2.5,
+            ..DefaultMetadata::new(IndexVersion::V1)
+        };
+
+        // Older versions have the layout of the metadata before the average document length
+        let old_layout = bincode::serialize(&(IndexVersion::V1, 7usize)).unwrap();
+        assert_eq!(bincode::serialize(&v1).unwrap(), old_layout);
+        let old: DefaultMetadata = bincode::deserialize(&old_layout).unwrap();
+        assert_eq!(old.version, IndexVersion::V1);
+        assert_eq!(old.document_count, 7);
+        assert_eq!(old.avg_doc_length, 0.0);
+
+        let enc = bincode::serialize(&v4).unwrap();
+        assert_eq!(enc.len(), old_layout.len() + 4);
+        let dec: DefaultMetadata = bincode::deserialize(&enc).unwrap();
+        assert_eq!(dec.avg_doc_length, 2.5);
+    }
+}
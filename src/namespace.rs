@@ -0,0 +1,160 @@
+use crate::{
+    build::IndexBuilder,
+    error::Error,
+    metadata::Metadata,
+    traits::{Decodable, Encodable},
+    Index,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufReader, ErrorKind, Read, Write},
+    path::Path,
+};
+
+type Result<T> = std::result::Result<T, Error>;
+
+impl<D: Decodable + Encodable> IndexBuilder<D> {
+    /// Like `build_to_writer` but writes the index under `namespace`, allowing to write multiple
+    /// indexes into one file, e.g. one per language. Since the namespaces written before are
+    /// unknown here, duplicate names are not rejected. Use `Index::write_namespaces` for that
+    pub fn build_to_writer_namespaced<W: Write, M: Serialize>(
+        self,
+        out: W,
+        metadata: M,
+        namespace: &str,
+    ) -> Result<Index<D, M>> {
+        let index = self.build(metadata)?;
+        write_namespace(out, namespace, &index)?;
+        Ok(index)
+    }
+}
+
+impl<D: Decodable, M: Serialize> Index<D, M> {
+    /// Writes multiple indexes into one file, each stored under its own namespace. A single index
+    /// can be loaded again using `Index::open_namespace`
+    pub fn write_namespaces<W: Write>(mut out: W, indexes: &[(&str, &Index<D, M>)]) -> Result<()> {
+        let mut names = HashSet::new();
+        if let Some((name, _)) = indexes.iter().find(|(name, _)| !names.insert(*name)) {
+            return Err(Error::DuplicateNamespace(name.to_string()));
+        }
+
+        for (namespace, index) in indexes {
+            write_namespace(&mut out, namespace, index)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<D: Decodable, M: Metadata + DeserializeOwned + Serialize> Index<D, M> {
+    /// Opens the index stored under `namespace` in a file written by `Index::write_namespaces`
+    /// or `IndexBuilder::build_to_writer_namespaced`
+    #[inline]
+    pub fn open_namespace<P: AsRef<Path>>(file: P, namespace: &str) -> Result<Index<D, M>> {
+        Self::from_reader_namespace(BufReader::new(File::open(file)?), namespace)
+    }
+
+    /// Reads the index stored under `namespace`. The indexes of all other namespaces get skipped
+    /// without being loaded
+    pub fn from_reader_namespace<R: Read>(mut reader: R, namespace: &str) -> Result<Index<D, M>> {
+        loop {
+            let name_len = match reader.read_u64::<LittleEndian>() {
+                Ok(len) => len,
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            };
+
+            // Don't trust the lengths for allocating since they might be corrupted
+            let mut name = vec![];
+            (&mut reader).take(name_len).read_to_end(&mut name)?;
+            if name.len() as u64 != name_len {
+                return Err(Error::InvalidIndex);
+            }
+
+            let len = reader.read_u64::<LittleEndian>()?;
+            let mut data = (&mut reader).take(len);
+            if name == namespace.as_bytes() {
+                return Self::from_reader(data);
+            }
+
+            std::io::copy(&mut data, &mut std::io::sink())?;
+            if data.limit() != 0 {
+                return Err(Error::InvalidIndex);
+            }
+        }
+
+        Err(Error::NamespaceNotFound(namespace.to_string()))
+    }
+}
+
+/// Writes `index` prefixed with its namespace and its length
+fn write_namespace<W: Write, D: Decodable, M: Serialize>(
+    mut out: W,
+    namespace: &str,
+    index: &Index<D, M>,
+) -> Result<()> {
+    let data = bincode::serialize(index)?;
+    out.write_u64::<LittleEndian>(namespace.len() as u64)?;
+    out.write_all(namespace.as_bytes())?;
+    out.write_u64::<LittleEndian>(data.len() as u64)?;
+    out.write_all(&data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DefaultMetadata;
+
+    fn build(docs: &[&[&str]]) -> IndexBuilder<u32> {
+        let mut builder = IndexBuilder::new();
+        for (pos, terms) in docs.iter().enumerate() {
+            builder.insert_new_vec(pos as u32, terms);
+        }
+        builder
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let mut out = vec![];
+        let en = build(&[&["dog", "cat"], &["cat", "mouse"]])
+            .build_to_writer_namespaced(&mut out, DefaultMetadata::default(), "en")
+            .unwrap();
+        let de = build(&[&["hund"], &["katze", "maus"], &["maus"]])
+            .build_to_writer_namespaced(&mut out, DefaultMetadata::default(), "de")
+            .unwrap();
+
+        for (namespace, index) in [("en", &en), ("de", &de)].iter().copied() {
+            let read =
+                Index::<u32, DefaultMetadata>::from_reader_namespace(out.as_slice(), namespace)
+                    .unwrap();
+            assert_eq!(read.get_indexer().len(), index.get_indexer().len());
+            assert_eq!(
+                read.get_vector_store().len(),
+                index.get_vector_store().len()
+            );
+        }
+        let read = Index::<u32, DefaultMetadata>::from_reader_namespace(out.as_slice(), "de");
+        assert!(read.unwrap().get_indexer().get_term("katze").is_some());
+
+        let missing = Index::<u32, DefaultMetadata>::from_reader_namespace(out.as_slice(), "fr");
+        assert!(matches!(missing, Err(Error::NamespaceNotFound(name)) if name == "fr"));
+        let truncated =
+            Index::<u32, DefaultMetadata>::from_reader_namespace(&out[..out.len() - 1], "de");
+        assert!(truncated.is_err());
+
+        let mut bundle = vec![];
+        Index::write_namespaces(&mut bundle, &[("en", &en), ("de", &de)]).unwrap();
+        let path = std::env::temp_dir().join("vsm_test_open_namespace");
+        std::fs::write(&path, &bundle).unwrap();
+        let opened = Index::<u32, DefaultMetadata>::open_namespace(&path, "en").unwrap();
+        assert!(opened.get_indexer().get_term("dog").is_some());
+        std::fs::remove_file(&path).unwrap();
+
+        let duplicate = Index::write_namespaces(&mut vec![], &[("en", &en), ("en", &de)]);
+        assert!(matches!(duplicate, Err(Error::DuplicateNamespace(name)) if name == "en"));
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{traits::Encodable, Error};
 use byteorder::WriteBytesExt;
-use std::convert::TryInto;
+use std::{borrow::Cow, convert::TryInto};
 
 /// A term in the index
 #[derive(Debug, Clone)]
@@ -32,6 +32,30 @@ impl IndexTerm {
         }
     }
 
+    /// Decodes an index item from raw data. Other than `decode` this returns an error if the data
+    /// is too short or the text isn't valid UTF-8
+    pub fn try_decode(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < 4 {
+            return Err(Error::Decode);
+        }
+
+        let doc_frequency = u32::from_le_bytes(data[0..4].try_into().unwrap());
+        let text = String::from_utf8(data[4..].to_vec())?;
+        Ok(Self {
+            text,
+            doc_frequency,
+        })
+    }
+
+    /// Decodes an index item from raw data, writing its text into `text_buf` to reuse its
+    /// allocation. Returns the items document frequency. Panics if the data is malformed
+    #[inline]
+    pub fn decode_into(data: &[u8], text_buf: &mut String) -> u32 {
+        text_buf.clear();
+        text_buf.push_str(&String::from_utf8_lossy(&data[4..]));
+        u32::from_le_bytes(data[0..4].try_into().unwrap())
+    }
+
     /// Get a reference to the index item's text.
     #[inline]
     pub fn text(&self) -> &str {
@@ -45,6 +69,47 @@ impl IndexTerm {
     }
 }
 
+/// A term in the index borrowing its text from the encoded data
+#[derive(Debug, Clone)]
+pub struct IndexTermRef<'a> {
+    /// The terms text/value. Only owned if the data contained invalid UTF-8
+    text: Cow<'a, str>,
+    /// number of documents with this term
+    doc_frequency: u32,
+}
+
+impl<'a> IndexTermRef<'a> {
+    /// Decodes an index item from raw data without copying its text. Panics if the data is
+    /// malformed
+    #[inline(always)]
+    pub fn decode(data: &'a [u8]) -> Self {
+        let doc_frequency = u32::from_le_bytes(data[0..4].try_into().unwrap());
+        let text = String::from_utf8_lossy(&data[4..]);
+        Self {
+            text,
+            doc_frequency,
+        }
+    }
+
+    /// Get a reference to the index item's text.
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Get the index item's frequency.
+    #[inline]
+    pub fn doc_frequency(&self) -> u32 {
+        self.doc_frequency
+    }
+
+    /// Converts the item into an owned `IndexTerm`
+    #[inline]
+    pub fn to_owned_term(&self) -> IndexTerm {
+        IndexTerm::new(self.text.to_string(), self.doc_frequency)
+    }
+}
+
 impl AsRef<str> for IndexTerm {
     #[inline]
     fn as_ref(&self) -> &str {
@@ -62,3 +127,55 @@ impl Encodable for IndexTerm {
         Ok(out)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use byteorder::LittleEndian;
+
+    #[test]
+    fn test_try_decode() {
+        assert!(matches!(IndexTerm::try_decode(&[1, 0]), Err(Error::Decode)));
+        assert!(matches!(
+            IndexTerm::try_decode(&[1, 0, 0, 0, 0xff]),
+            Err(Error::UTF8Error)
+        ));
+
+        let enc = IndexTerm::new("car".to_string(), 7)
+            .encode::<LittleEndian>()
+            .unwrap();
+        let term = IndexTerm::try_decode(&enc).unwrap();
+        assert_eq!(term.text(), "car");
+        assert_eq!(term.doc_frequency(), 7);
+    }
+
+    #[test]
+    fn test_decode_borrowed() {
+        let terms = ["car", "drive", "a"];
+        let encoded: Vec<_> = terms
+            .iter()
+            .enumerate()
+            .map(|(pos, text)| {
+                IndexTerm::new(text.to_string(), pos as u32)
+                    .encode::<LittleEndian>()
+                    .unwrap()
+            })
+            .collect();
+
+        let mut buf = String::with_capacity(16);
+        let buf_ptr = buf.as_ptr();
+
+        for (pos, data) in encoded.iter().enumerate() {
+            let term = IndexTermRef::decode(data);
+            assert!(matches!(term.text, Cow::Borrowed(_)));
+            assert_eq!(term.text(), terms[pos]);
+            assert_eq!(term.doc_frequency(), pos as u32);
+
+            assert_eq!(IndexTerm::decode_into(data, &mut buf), pos as u32);
+            assert_eq!(buf, terms[pos]);
+            assert_eq!(buf.as_ptr(), buf_ptr);
+
+            assert_eq!(term.to_owned_term().text(), IndexTerm::decode(data).text());
+        }
+    }
+}
@@ -1,11 +1,15 @@
 pub mod item;
 
-use self::item::IndexTerm;
-use crate::{build::term_store::TermStoreBuilder, error::Error, traits::Encodable};
-use byteorder::LittleEndian;
+use self::item::{IndexTerm, IndexTermRef};
+use crate::{
+    build::term_store::TermStoreBuilder,
+    error::Error,
+    traits::{Decodable, Encodable, Indexable, MAX_PREALLOC},
+};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 use indexed_file::mem_file::MemFile;
 use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
+use std::{cmp::Ordering, io::Read};
 
 /// An in memory TermIndexer that allows efficient indexing of terms which is requried for document
 /// vectors being calculated.
@@ -14,6 +18,26 @@ pub struct TermIndexer {
     index: MemFile,
     tot_documents: usize,
     sort_index: Vec<u32>,
+    /// Set if the index was built without the texts of the terms, making all texts empty
+    #[serde(default)]
+    without_text: bool,
+    #[cfg(feature = "genbktree")]
+    #[serde(skip)]
+    term_tree: TermTree,
+}
+
+/// Tree of all terms for fuzzy lookups. It gets built on the first lookup
+#[cfg(feature = "genbktree")]
+#[derive(Default)]
+struct TermTree(std::sync::OnceLock<bktree::BkTree<String>>);
+
+#[cfg(feature = "genbktree")]
+impl std::fmt::Debug for TermTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("TermTree")
+            .field(&self.0.get().is_some())
+            .finish()
+    }
 }
 
 impl TermIndexer {
@@ -29,6 +53,51 @@ impl TermIndexer {
         self.len() == 0
     }
 
+    /// Returns the total amount of documents the term frequencies are based on
+    #[inline]
+    pub fn total_documents(&self) -> usize {
+        self.tot_documents
+    }
+
+    /// Sets the total amount of documents the term frequencies are based on. This has to be updated
+    /// if documents get added or removed to keep the inverse document frequency correct
+    #[inline]
+    pub fn set_total_documents(&mut self, n: usize) {
+        self.tot_documents = n;
+    }
+
+    /// Increases the document frequency of the term with dimension `dim` by `count`. Has to be
+    /// called for each term of documents getting added to keep the inverse document frequency
+    /// correct. Since the frequency is stored with a fixed size the encoded term keeps its length
+    /// and gets replaced in place. Returns `false` if the term doesn't exist
+    pub fn add_doc_frequency(&mut self, dim: usize, count: u32) -> bool {
+        let term = match self.load_term(dim) {
+            Some(term) => term,
+            None => return false,
+        };
+
+        let doc_freq = term.doc_frequency().saturating_add(count);
+        let term = IndexTerm::new(term.text().to_string(), doc_freq);
+        let enc = term.encode::<LittleEndian>().expect("Invalid item");
+        self.index.replace(dim, &enc);
+        true
+    }
+
+    /// Decreases the document frequency of the term with dimension `dim` by `count`. Counterpart of
+    /// `add_doc_frequency` for removed documents. Returns `false` if the term doesn't exist
+    pub fn sub_doc_frequency(&mut self, dim: usize, count: u32) -> bool {
+        let term = match self.load_term(dim) {
+            Some(term) => term,
+            None => return false,
+        };
+
+        let doc_freq = term.doc_frequency().saturating_sub(count);
+        let term = IndexTerm::new(term.text().to_string(), doc_freq);
+        let enc = term.encode::<LittleEndian>().expect("Invalid item");
+        self.index.replace(dim, &enc);
+        true
+    }
+
     /// Finds a term in the termindex
     /// Term_str -> TermObj
     #[inline]
@@ -36,13 +105,84 @@ impl TermIndexer {
         self.get_term_raw(term).map(|i| i.1)
     }
 
+    /// Returns `false` if the index was built without the texts of the terms using
+    /// `IndexBuilder::with_term_text`. Looking up terms by their text always fails in this case
+    #[inline]
+    pub fn has_text(&self) -> bool {
+        !self.without_text
+    }
+
+    #[inline]
+    pub(crate) fn set_has_text(&mut self, has_text: bool) {
+        self.without_text = !has_text;
+    }
+
     /// Term_str -> Dimension
     #[inline]
     pub fn get_term(&self, term: &str) -> Option<usize> {
         self.get_term_raw(term).map(|i| i.0)
     }
 
-    /// Gets a term by its dimension
+    /// Resolves the dimensions of multiple terms at once. The order of `terms` is preserved
+    pub fn get_terms(&self, terms: &[&str]) -> Vec<Option<usize>> {
+        let mut out = vec![None; terms.len()];
+        if self.without_text {
+            return out;
+        }
+
+        let mut order: Vec<usize> = (0..terms.len()).collect();
+        order.sort_unstable_by_key(|i| terms[*i]);
+
+        // Terms are resolved in ascending order, so each search only has to look at the part of
+        // the index following the previous term
+        let mut start = 0;
+        for i in order {
+            let term = terms[i];
+            start = self.lower_bound(term, start);
+            if start == self.index.len() {
+                break;
+            }
+
+            let dim = self.sorted_dim(start);
+            if IndexTermRef::decode(self.index.get_unchecked(dim)).text() == term {
+                out[i] = Some(dim);
+            }
+        }
+
+        out
+    }
+
+    /// Returns the position of the first term in sort order which isn't smaller than `term`,
+    /// starting the search at position `start`
+    fn lower_bound(&self, term: &str, start: usize) -> usize {
+        let (mut low, mut high) = (start, self.index.len());
+
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let item = IndexTermRef::decode(self.index.get_unchecked(self.sorted_dim(mid)));
+            if item.text() < term {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        low
+    }
+
+    /// Maps a position in sort order to the dimension of the term
+    #[inline]
+    fn sorted_dim(&self, pos: usize) -> usize {
+        if self.is_sorted() {
+            pos
+        } else {
+            self.sort_index[pos] as usize
+        }
+    }
+
+    /// Gets a term by its dimension. Returns `None` if the dimension doesn't exist. Terms of
+    /// decoded indexes are validated upfront, so malformed terms only panic if the indexer got
+    /// constructed in a different way. See `try_load_term`
     /// Dimension -> Term
     #[inline]
     pub fn load_term(&self, dimension: usize) -> Option<IndexTerm> {
@@ -50,12 +190,71 @@ impl TermIndexer {
         Some(IndexTerm::decode(res))
     }
 
+    /// Like `load_term` but returns an error if the term is malformed instead of panicking
+    #[inline]
+    pub fn try_load_term(&self, dimension: usize) -> Result<Option<IndexTerm>, Error> {
+        self.index
+            .get(dimension)
+            .map(IndexTerm::try_decode)
+            .transpose()
+    }
+
     /// Returns an iterator over all Indexed terms
     #[inline]
     pub fn iter(&self) -> impl Iterator<Item = IndexTerm> + '_ {
         self.index.iter().map(IndexTerm::decode)
     }
 
+    /// Returns an iterator over all indexed terms. Other than `iter` malformed terms are returned
+    /// as error instead of panicking
+    #[inline]
+    pub fn iter_checked(&self) -> impl Iterator<Item = Result<IndexTerm, Error>> + '_ {
+        self.index.iter().map(IndexTerm::try_decode)
+    }
+
+    /// Returns an error if any of the terms is malformed. Lookups decode terms without checking
+    /// them, so this has to pass for all indexers read from untrusted data
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        self.iter_checked().try_for_each(|term| term.map(|_| ()))
+    }
+
+    /// Returns all terms with an edit distance of at most `max_dist` to `term`, together with
+    /// their distance. The terms are looked up in the tree of `gen_term_tree`, which gets built
+    /// once on the first call
+    #[cfg(feature = "genbktree")]
+    pub fn find_similar(&self, term: &str, max_dist: usize) -> Vec<(IndexTerm, usize)> {
+        if self.without_text {
+            return vec![];
+        }
+
+        let tree = self.term_tree.0.get_or_init(|| self.gen_term_tree());
+        tree.find(term.to_string(), max_dist)
+            .into_iter()
+            .filter_map(|(text, dist)| Some((self.find_term(text)?, dist)))
+            .collect()
+    }
+
+    /// Counts the terms by their document frequency using `buckets` buckets of equal width, which
+    /// span from the lowest to the highest document frequency. The first bucket contains the
+    /// rarest terms
+    pub fn df_histogram(&self, buckets: usize) -> Vec<usize> {
+        let mut histogram = vec![0; buckets];
+        if buckets == 0 {
+            return histogram;
+        }
+
+        let min = self.iter().map(|i| i.doc_frequency()).min().unwrap_or(0);
+        let max = self.iter().map(|i| i.doc_frequency()).max().unwrap_or(0);
+        let width = (max - min + 1) as f64 / buckets as f64;
+
+        for term in self.iter() {
+            let bucket = ((term.doc_frequency() - min) as f64 / width) as usize;
+            histogram[bucket.min(buckets - 1)] += 1;
+        }
+
+        histogram
+    }
+
     /// Returns `true` if the items are sorted and no custom sort index exists
     #[inline]
     pub fn is_sorted(&self) -> bool {
@@ -67,14 +266,41 @@ impl TermIndexer {
         let mut terms = Vec::with_capacity(self.index.len());
 
         for i in self.index.iter() {
-            terms.push(IndexTerm::decode(&i).text().to_string());
+            terms.push(IndexTermRef::decode(i).text().to_string());
         }
 
         terms.into_iter().collect::<bktree::BkTree<_>>()
     }
 
-    /// Builds a new TermIndexer from TermStoreBuilder.
-    pub(crate) fn build(ts_builder: TermStoreBuilder) -> Result<Self, Error> {
+    /// Returns an iterator over all indexed terms ordered by their document frequency in
+    /// descending order. Terms with the same frequency are ordered by their text. This decodes
+    /// and sorts all terms upfront, taking `O(n log n)` time and `O(n)` memory
+    pub fn iter_by_frequency(&self) -> impl Iterator<Item = IndexTerm> {
+        let mut terms: Vec<_> = self.iter().collect();
+        terms.sort_by(|a, b| {
+            b.doc_frequency()
+                .cmp(&a.doc_frequency())
+                .then_with(|| a.text().cmp(b.text()))
+        });
+        terms.into_iter()
+    }
+
+    /// Returns an iterator over all indexed terms ordered by their text
+    #[inline]
+    pub fn iter_sorted(&self) -> impl Iterator<Item = IndexTerm> + '_ {
+        (0..self.len()).map(move |pos| {
+            let pos = if self.is_sorted() {
+                pos
+            } else {
+                self.sort_index[pos] as usize
+            };
+            self.load_term(pos).expect("Invalid sort index")
+        })
+    }
+
+    /// Builds a new TermIndexer from TermStoreBuilder. If `store_text` is `false` all terms are
+    /// stored with an empty text.
+    pub(crate) fn build(ts_builder: TermStoreBuilder, store_text: bool) -> Result<Self, Error> {
         let sort_index = vec![];
 
         let mut terms = ts_builder
@@ -82,7 +308,12 @@ impl TermIndexer {
             .iter()
             .map(|(term, id)| {
                 let doc_freq = ts_builder.doc_frequencies().get(id).unwrap();
-                let term = IndexTerm::new(term.to_string(), *doc_freq);
+                let text = if store_text {
+                    term.to_string()
+                } else {
+                    String::new()
+                };
+                let term = IndexTerm::new(text, *doc_freq);
                 let pos = ts_builder.get_sorted_term_pos(*id);
                 (pos, term)
             })
@@ -99,9 +330,40 @@ impl TermIndexer {
             index,
             tot_documents: 0,
             sort_index,
+            without_text: !store_text,
+            #[cfg(feature = "genbktree")]
+            term_tree: TermTree::default(),
         })
     }
 
+    /// Builds a new TermIndexer out of `terms` ordered by their dimension. If the terms aren't
+    /// sorted by their text a custom sort index gets built
+    #[cfg(feature = "serde_json")]
+    pub(crate) fn from_terms(terms: Vec<IndexTerm>, tot_documents: usize) -> Result<Self, Error> {
+        let sorted = terms.windows(2).all(|w| w[0].text() <= w[1].text());
+
+        let mut index = MemFile::with_capacity(terms.len());
+        for term in terms {
+            index.insert(&term.encode::<LittleEndian>()?);
+        }
+
+        let mut indexer = Self {
+            index,
+            tot_documents,
+            sort_index: vec![],
+            without_text: false,
+            #[cfg(feature = "genbktree")]
+            term_tree: TermTree::default(),
+        };
+
+        if !sorted {
+            indexer.build_cust_sort();
+            indexer.update_sort_index();
+        }
+
+        Ok(indexer)
+    }
+
     /// Builds a new cust sort mapping index
     pub fn build_cust_sort(&mut self) {
         if !self.is_sorted() {
@@ -124,6 +386,11 @@ impl TermIndexer {
 
         self.update_sort_index();
 
+        #[cfg(feature = "genbktree")]
+        {
+            self.term_tree = TermTree::default();
+        }
+
         Some(id)
     }
 
@@ -138,6 +405,10 @@ impl TermIndexer {
     }
 
     fn get_term_raw(&self, term: &str) -> Option<(usize, IndexTerm)> {
+        if self.without_text {
+            return None;
+        }
+
         if self.is_sorted() {
             // No special sort-mapping specified so we can assume terms are sorted
             return gen_bin_search_by(&self.index, self.index.len(), |idx, pos| {
@@ -165,7 +436,166 @@ impl TermIndexer {
             index: self.index.clone(),
             tot_documents: self.tot_documents,
             sort_index: self.sort_index.clone(),
+            without_text: self.without_text,
+            #[cfg(feature = "genbktree")]
+            term_tree: TermTree::default(),
+        }
+    }
+}
+
+/// Layout of a `TermIndexer` serialized before indexes could be built without term texts
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+pub(crate) struct LegacyTermIndexer {
+    index: MemFile,
+    tot_documents: usize,
+    sort_index: Vec<u32>,
+}
+
+#[cfg(test)]
+impl From<&TermIndexer> for LegacyTermIndexer {
+    fn from(indexer: &TermIndexer) -> Self {
+        Self {
+            index: indexer.index.clone(),
+            tot_documents: indexer.tot_documents,
+            sort_index: indexer.sort_index.clone(),
+        }
+    }
+}
+
+impl From<LegacyTermIndexer> for TermIndexer {
+    #[inline]
+    fn from(legacy: LegacyTermIndexer) -> Self {
+        Self {
+            index: legacy.index,
+            tot_documents: legacy.tot_documents,
+            sort_index: legacy.sort_index,
+            without_text: false,
+            #[cfg(feature = "genbktree")]
+            term_tree: TermTree::default(),
+        }
+    }
+}
+
+impl Indexable for TermIndexer {
+    #[inline]
+    fn index_of(&self, term: &str) -> Option<u32> {
+        self.get_term(term).map(|i| i as u32)
+    }
+
+    #[inline]
+    fn word_occurrence(&self, dim: u32) -> Option<usize> {
+        self.load_term(dim as usize)
+            .map(|i| i.doc_frequency() as usize)
+    }
+}
+
+impl Encodable for TermIndexer {
+    fn encode<T: ByteOrder>(&self) -> Result<Vec<u8>, Error> {
+        let mut out = vec![];
+
+        out.write_u64::<T>(self.tot_documents as u64)?;
+        out.extend(self.index.encode::<T>()?);
+        out.extend(self.sort_index.encode::<T>()?);
+
+        Ok(out)
+    }
+}
+
+impl Decodable for TermIndexer {
+    fn decode<T: ByteOrder, R: Read>(mut data: R) -> Result<Self, Error> {
+        let tot_documents = data.read_u64::<T>()? as usize;
+        let index = MemFile::decode::<T, _>(&mut data)?;
+        let sort_index = Vec::<u32>::decode::<T, _>(&mut data)?;
+
+        let indexer = Self {
+            index,
+            tot_documents,
+            sort_index,
+            without_text: false,
+            #[cfg(feature = "genbktree")]
+            term_tree: TermTree::default(),
+        };
+
+        // Validate all terms so corrupted data doesn't panic later on
+        indexer.validate()?;
+        Ok(indexer)
+    }
+}
+
+impl TermIndexer {
+    /// Encodes the indexer using front coding. Each term only stores the length of the prefix it
+    /// shares with the term of the previous dimension followed by the remaining suffix. Since
+    /// terms are sorted this saves a lot of space for large vocabularies
+    pub(crate) fn encode_front_coded<T: ByteOrder>(&self) -> Result<Vec<u8>, Error> {
+        let mut out = vec![];
+
+        out.write_u64::<T>(self.tot_documents as u64)?;
+        out.write_u32::<T>(self.index.len() as u32)?;
+
+        let mut prev: Vec<u8> = vec![];
+        for raw in self.index.iter() {
+            let term = IndexTerm::try_decode(raw)?;
+            let text = term.text().as_bytes();
+
+            let shared = prev
+                .iter()
+                .zip(text)
+                .take(u16::MAX as usize)
+                .take_while(|(a, b)| a == b)
+                .count();
+            let suffix = &text[shared..];
+
+            out.write_u32::<T>(term.doc_frequency())?;
+            out.write_u16::<T>(shared as u16)?;
+            out.write_u32::<T>(suffix.len() as u32)?;
+            out.extend(suffix);
+
+            prev.clear();
+            prev.extend(text);
         }
+
+        out.extend(self.sort_index.encode::<T>()?);
+        Ok(out)
+    }
+
+    /// Decodes an indexer encoded with `encode_front_coded`, reconstructing each term from the
+    /// term of the previous dimension
+    pub(crate) fn decode_front_coded<T: ByteOrder, R: Read>(mut data: R) -> Result<Self, Error> {
+        let tot_documents = data.read_u64::<T>()? as usize;
+        let len = data.read_u32::<T>()? as usize;
+
+        // Don't trust the count for allocating since it might be corrupted
+        let mut index = MemFile::with_capacity(len.min(MAX_PREALLOC));
+        let mut prev: Vec<u8> = vec![];
+        for _ in 0..len {
+            let doc_frequency = data.read_u32::<T>()?;
+            let shared = data.read_u16::<T>()? as usize;
+            let suffix_len = data.read_u32::<T>()? as u64;
+            if shared > prev.len() {
+                return Err(Error::Decode);
+            }
+
+            prev.truncate(shared);
+            let read = (&mut data).take(suffix_len).read_to_end(&mut prev)?;
+            if read as u64 != suffix_len {
+                return Err(Error::Decode);
+            }
+
+            let term = IndexTerm::new(String::from_utf8(prev.clone())?, doc_frequency);
+            index.insert(&term.encode::<LittleEndian>()?);
+        }
+
+        let sort_index = Vec::<u32>::decode::<T, _>(&mut data)?;
+
+        Ok(Self {
+            index,
+            tot_documents,
+            sort_index,
+            without_text: false,
+            #[cfg(feature = "genbktree")]
+            term_tree: TermTree::default(),
+        })
     }
 }
 
@@ -195,3 +625,168 @@ where
 
     Err(left)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::build::IndexBuilder;
+
+    fn test_indexer() -> TermIndexer {
+        let mut builder = IndexBuilder::new();
+        builder.insert_new_vec(0u32, &["to", "drive", "a", "car"]);
+        builder.insert_new_vec(1u32, &["to", "have", "a", "call"]);
+        builder.insert_new_vec(2u32, &["to", "make", "a", "stand", "a"]);
+        builder.build(()).unwrap().indexer
+    }
+
+    #[test]
+    fn test_get_terms() {
+        let indexer = test_indexer();
+        let terms = ["call", "unknown", "a", "to", "stand"];
+
+        let single: Vec<_> = terms.iter().map(|i| indexer.get_term(i)).collect();
+        assert_eq!(indexer.get_terms(&terms), single);
+        assert_eq!(indexer.get_terms(&terms)[1], None);
+
+        // Terms inserted after building are resolved using the sort index
+        let mut indexer = test_indexer();
+        indexer.build_cust_sort();
+        indexer.insert_new(IndexTerm::new("b".to_string(), 1));
+        indexer.insert_new(IndexTerm::new("zzz".to_string(), 1));
+        let terms = ["zzz", "to", "b", "", "zzzz", "b", "a"];
+        let single: Vec<_> = terms.iter().map(|i| indexer.get_term(i)).collect();
+        assert_eq!(indexer.get_terms(&terms), single);
+        assert!(indexer.get_terms(&terms)[2].is_some());
+        assert!(indexer.get_terms(&[]).is_empty());
+    }
+
+    #[cfg(feature = "genbktree")]
+    #[test]
+    fn test_find_similar() {
+        let mut indexer = test_indexer();
+        let similar = |indexer: &TermIndexer| -> Vec<_> {
+            let mut similar: Vec<_> = indexer
+                .find_similar("cal", 1)
+                .into_iter()
+                .map(|(term, dist)| (term.text().to_string(), dist))
+                .collect();
+            similar.sort();
+            similar
+        };
+        assert_eq!(
+            similar(&indexer),
+            vec![("call".to_string(), 1), ("car".to_string(), 1)]
+        );
+
+        // Inserting a term has to rebuild the tree
+        indexer.build_cust_sort();
+        indexer.insert_new(IndexTerm::new("cat".to_string(), 1));
+        assert_eq!(
+            similar(&indexer),
+            vec![
+                ("call".to_string(), 1),
+                ("car".to_string(), 1),
+                ("cat".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_terms() {
+        let mut indexer = test_indexer();
+        assert!(indexer.validate().is_ok());
+        assert!(indexer.iter_checked().all(|i| i.is_ok()));
+
+        let dim = indexer.index.insert(&[1, 2]);
+        assert!(indexer.validate().is_err());
+        assert!(indexer.iter_checked().last().unwrap().is_err());
+        assert!(indexer.try_load_term(dim).is_err());
+        assert!(indexer.try_load_term(0).unwrap().is_some());
+        assert!(indexer.try_load_term(dim + 1).unwrap().is_none());
+
+        // Indexes read using bincode get validated as well
+        let mut index = IndexBuilder::new();
+        index.insert_new_vec(0u32, &["a", "b"]);
+        let mut index = index.build(crate::DefaultMetadata::default()).unwrap();
+        index.indexer.index.insert(&[1, 2]);
+        let out = bincode::serialize(&index).unwrap();
+        let read = crate::Index::<u32, crate::DefaultMetadata>::from_reader(out.as_slice());
+        assert!(matches!(read, Err(Error::Decode)));
+    }
+
+    #[test]
+    fn test_front_coding() {
+        let vocabulary = [
+            "drive", "driven", "driver", "drivers", "drives", "driving", "drove", "drück",
+            "drücken",
+        ];
+        let mut builder = IndexBuilder::new();
+        for (pos, term) in vocabulary.iter().enumerate() {
+            builder.insert_new_vec(pos as u32, &[term]);
+        }
+        let indexer = builder.build(()).unwrap().indexer;
+
+        let plain = indexer.encode::<LittleEndian>().unwrap();
+        let coded = indexer.encode_front_coded::<LittleEndian>().unwrap();
+        assert!(coded.len() < plain.len());
+
+        let decoded = TermIndexer::decode_front_coded::<LittleEndian, _>(coded.as_slice()).unwrap();
+        assert_eq!(decoded.total_documents(), indexer.total_documents());
+        let terms = |indexer: &TermIndexer| -> Vec<_> {
+            indexer
+                .iter()
+                .map(|i| (i.text().to_string(), i.doc_frequency()))
+                .collect()
+        };
+        assert_eq!(terms(&decoded), terms(&indexer));
+        assert_eq!(decoded.get_term("drivers"), indexer.get_term("drivers"));
+
+        let truncated = &coded[..coded.len() - 6];
+        assert!(TermIndexer::decode_front_coded::<LittleEndian, _>(truncated).is_err());
+
+        let mut corrupted = coded;
+        corrupted[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(TermIndexer::decode_front_coded::<LittleEndian, _>(corrupted.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_df_histogram() {
+        let indexer = test_indexer();
+
+        for buckets in 1..6 {
+            let histogram = indexer.df_histogram(buckets);
+            assert_eq!(histogram.len(), buckets);
+            assert_eq!(histogram.iter().sum::<usize>(), indexer.len());
+        }
+
+        // "to" and "a" occur in all three documents, all other terms only in one
+        assert_eq!(indexer.df_histogram(3), vec![6, 0, 2]);
+        assert!(indexer.df_histogram(0).is_empty());
+        assert_eq!(TermIndexer::default().df_histogram(2), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_indexable() {
+        let indexer = test_indexer();
+        let dim = indexer.index_of("to").unwrap();
+        assert_eq!(dim as usize, indexer.get_term("to").unwrap());
+        assert_eq!(indexer.word_occurrence(dim), Some(3));
+        assert_eq!(indexer.index_of("unknown"), None);
+        assert_eq!(indexer.word_occurrence(indexer.len() as u32), None);
+    }
+
+    #[test]
+    fn test_iter_by_frequency() {
+        let indexer = test_indexer();
+        let terms: Vec<_> = indexer.iter_by_frequency().collect();
+        assert_eq!(terms.len(), indexer.len());
+
+        let max = indexer.iter().map(|i| i.doc_frequency()).max().unwrap();
+        assert_eq!(terms[0].doc_frequency(), max);
+        assert_eq!(terms[0].text(), "a");
+        assert_eq!(terms[1].text(), "to");
+        assert!(terms
+            .windows(2)
+            .all(|i| i[0].doc_frequency() >= i[1].doc_frequency()));
+    }
+}
@@ -1,7 +1,12 @@
 use crate::error::Error;
 use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt};
+use indexed_file::mem_file::MemFile;
 use std::io::{Cursor, Read};
 
+/// Maximum amount of items to allocate upfront for a count read from untrusted data. Bigger
+/// collections grow while their items get read, so corrupted counts can't force huge allocations
+pub(crate) const MAX_PREALLOC: usize = 4096;
+
 pub trait SizedSerialize {
     fn size() -> usize;
 }
@@ -14,6 +19,15 @@ pub trait Decodable: Sized {
     fn decode<T: ByteOrder, R: Read>(data: R) -> Result<Self, Error>;
 }
 
+/// Types resolving terms to dimensions
+pub trait Indexable {
+    /// Returns the dimension of `term`
+    fn index_of(&self, term: &str) -> Option<u32>;
+
+    /// Returns the amount of documents containing the term of dimension `dim`
+    fn word_occurrence(&self, dim: u32) -> Option<usize>;
+}
+
 impl Encodable for u32 {
     #[inline]
     fn encode<T: ByteOrder>(&self) -> Result<Vec<u8>, Error> {
@@ -78,7 +92,7 @@ impl<DE: Decodable + SizedSerialize> Decodable for Vec<DE> {
     #[inline]
     fn decode<T: ByteOrder, R: Read>(mut data: R) -> Result<Self, Error> {
         let len = data.read_u32::<T>()?;
-        let mut out_list = Vec::with_capacity(len as usize);
+        let mut out_list = Vec::with_capacity((len as usize).min(MAX_PREALLOC));
 
         let mut buf = vec![0u8; DE::size()];
         for _ in 0..len {
@@ -90,6 +104,42 @@ impl<DE: Decodable + SizedSerialize> Decodable for Vec<DE> {
     }
 }
 
+impl Encodable for MemFile {
+    fn encode<T: ByteOrder>(&self) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::new();
+
+        out.write_u32::<T>(self.len() as u32)?;
+
+        for entry in self.iter() {
+            out.write_u32::<T>(entry.len() as u32)?;
+            out.extend_from_slice(entry);
+        }
+
+        Ok(out)
+    }
+}
+
+impl Decodable for MemFile {
+    fn decode<T: ByteOrder, R: Read>(mut data: R) -> Result<Self, Error> {
+        let len = data.read_u32::<T>()? as usize;
+        let mut out = MemFile::with_capacity(len.min(MAX_PREALLOC));
+
+        let mut buf = vec![];
+        for _ in 0..len {
+            // Don't trust the length for allocating since it might be corrupted
+            let entry_len = data.read_u32::<T>()? as u64;
+            buf.clear();
+            (&mut data).take(entry_len).read_to_end(&mut buf)?;
+            if buf.len() as u64 != entry_len {
+                return Err(Error::Decode);
+            }
+            out.insert(&buf);
+        }
+
+        Ok(out)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use byteorder::LittleEndian;
@@ -103,4 +153,29 @@ mod test {
         let decoded = Vec::<u32>::decode::<LittleEndian, _>(Cursor::new(encoded)).unwrap();
         assert_eq!(decoded, input);
     }
+
+    #[test]
+    fn test_decode_corrupted_len() {
+        let mut mem_file = MemFile::with_capacity(2);
+        mem_file.insert(b"abc");
+        mem_file.insert(b"de");
+        let encoded = mem_file.encode::<LittleEndian>().unwrap();
+        let decoded = MemFile::decode::<LittleEndian, _>(encoded.as_slice()).unwrap();
+        assert_eq!(
+            decoded.iter().collect::<Vec<_>>(),
+            mem_file.iter().collect::<Vec<_>>()
+        );
+
+        // Counts and lengths exceeding the data must fail without allocating for them
+        let mut corrupted = encoded.clone();
+        corrupted[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(MemFile::decode::<LittleEndian, _>(corrupted.as_slice()).is_err());
+
+        let mut corrupted = encoded;
+        corrupted[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(MemFile::decode::<LittleEndian, _>(corrupted.as_slice()).is_err());
+
+        let vec = u32::MAX.to_le_bytes();
+        assert!(Vec::<u32>::decode::<LittleEndian, _>(&vec[..]).is_err());
+    }
 }
@@ -1,6 +1,29 @@
-use crate::lock_step::LockStepIter;
+use crate::{
+    build::weights::TermWeight,
+    error::Error,
+    lock_step::{LockStepIter, LockStepOuterIter},
+    traits::{Decodable, Encodable, Indexable},
+};
+use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt};
 use serde::{Deserialize, Serialize};
-use std::slice::IterMut;
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+    slice::IterMut,
+};
+
+/// Defines how to handle a dimension which already exists in a vector
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conflict {
+    /// Add the new value to the existing one
+    Sum,
+    /// Overwrite the existing value
+    Replace,
+    /// Keep the existing value
+    Keep,
+    /// Keep the bigger one of both values
+    Max,
+}
 
 /// A compressed n dimensional vector
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -33,6 +56,43 @@ impl Vector {
         vec
     }
 
+    /// Creates a new vector from terms and their count within a document. Each term is resolved
+    /// using `index` and weighted with `weight` using its count and document frequency. Returns
+    /// `None` if none of the terms could be resolved
+    pub fn from_term_counts<I: Indexable>(
+        index: &I,
+        counts: &[(&str, u32)],
+        weight: &dyn TermWeight,
+        total_docs: usize,
+    ) -> Option<Vector> {
+        let dims: Vec<_> = counts
+            .iter()
+            .filter(|(_, count)| *count > 0)
+            .filter_map(|(term, count)| {
+                let dim = index.index_of(term)?;
+                let df = index.word_occurrence(dim)?;
+                let tf = *count as usize;
+                Some((dim, weight.weight(*count as f32, tf, df, total_docs)))
+            })
+            .collect();
+
+        if dims.is_empty() {
+            return None;
+        }
+
+        Some(Vector::create_new_raw(dims))
+    }
+
+    /// Creates a new vector from `pairs` using a buffer of `pool` to avoid allocating a new one.
+    /// The vector can be given back to the pool using `VectorPool::release`
+    pub fn from_pool(pool: &mut VectorPool, pairs: &[(u32, f32)]) -> Self {
+        let mut inner = pool.acquire();
+        inner.extend_from_slice(pairs);
+        let mut vec = Self { inner, length: 0.0 };
+        vec.update();
+        vec
+    }
+
     /// Create a new Vec from raw values. `sparse` must be sorted by dimensions
     #[inline(always)]
     pub fn new_raw(sparse: Vec<(u32, f32)>, length: f32) -> Self {
@@ -42,6 +102,39 @@ impl Vector {
         }
     }
 
+    /// Creates a new vector by summing up all `vectors` scaled by their weight. Returns an empty
+    /// vector if `vectors` is empty. Values which aren't finite are set to `0.0`
+    pub fn blend(vectors: &[(Vector, f32)]) -> Vector {
+        let mut inner: Vec<(u32, f32)> = vec![];
+
+        for (vec, weight) in vectors {
+            let scaled = vec.iter().map(|(dim, val)| (dim, val * weight));
+            inner = LockStepOuterIter::new(inner.into_iter(), scaled)
+                .map(|(dim, a, b)| (dim, a.unwrap_or(0.0) + b.unwrap_or(0.0)))
+                .collect();
+        }
+
+        let mut blended = Vector::new_raw(inner, 0.0);
+        blended.sanitize();
+        blended
+    }
+
+    /// Encodes the vector into a base64 string using the same binary format used in indexes
+    #[cfg(feature = "base64")]
+    pub fn to_base64(&self) -> String {
+        let encoded = self
+            .encode::<byteorder::LittleEndian>()
+            .expect("Encoding into a Vec can't fail");
+        base64::encode(encoded)
+    }
+
+    /// Decodes a vector from a base64 string created with `to_base64`
+    #[cfg(feature = "base64")]
+    pub fn from_base64(s: &str) -> Result<Vector, Error> {
+        let data = base64::decode(s).map_err(|_| Error::Decode)?;
+        Self::decode::<byteorder::LittleEndian, _>(data.as_slice())
+    }
+
     /// Calculates the similarity between two vectors
     #[inline]
     pub fn similarity(&self, other: &Vector) -> f32 {
@@ -52,6 +145,46 @@ impl Vector {
         sc / (self.length * other.length)
     }
 
+    /// Calculates the similarity to each vector in `others` and writes them into `out`. The
+    /// previous content of `out` gets cleared, allowing to reuse its allocation
+    pub fn cosine_many(&self, others: &[Vector], out: &mut Vec<f32>) {
+        out.clear();
+        out.reserve(others.len());
+
+        let inv_len = 1.0 / self.length;
+        out.extend(others.iter().map(|other| {
+            let sc = self.scalar(other);
+            if sc == 0.0 {
+                return 0.0;
+            }
+            sc * inv_len / other.length
+        }));
+    }
+
+    /// Calculates the similarity between two vectors using only the dimensions in `dims`. The
+    /// lengths of both vectors are calculated over these dimensions as well. Returns `0.0` if
+    /// both vectors don't share any dimension in `dims`
+    pub fn similarity_on(&self, other: &Vector, dims: &HashSet<u32>) -> f32 {
+        let masked_len = |vec: &Vector| -> f32 {
+            vec.iter()
+                .filter(|(dim, _)| dims.contains(dim))
+                .map(|(_, val)| val * val)
+                .sum::<f32>()
+                .sqrt()
+        };
+
+        let scalar: f32 = self
+            .overlapping(other)
+            .filter(|(dim, _, _)| dims.contains(dim))
+            .map(|(_, a, b)| a * b)
+            .sum();
+        if scalar == 0.0 {
+            return 0.0;
+        }
+
+        scalar / (masked_len(self) * masked_len(other))
+    }
+
     /// Returns a mutable reference to the inner vector
     #[inline]
     pub fn sparse_vec_mut(&mut self) -> &mut Vec<(u32, f32)> {
@@ -79,6 +212,22 @@ impl Vector {
         LockStepIter::new(self.inner.iter().copied(), other.inner.iter().copied())
     }
 
+    /// Returns an iterator over all dimensions which are only in one of both vectors. The `bool`
+    /// is `true` if the dimension belongs to `self` and `false` if it belongs to `other`
+    #[inline]
+    pub fn symmetric_difference<'a>(
+        &'a self,
+        other: &'a Vector,
+    ) -> impl Iterator<Item = (u32, f32, bool)> + 'a {
+        LockStepOuterIter::new(self.inner.iter().copied(), other.inner.iter().copied()).filter_map(
+            |(dim, a, b)| match (a, b) {
+                (Some(a), None) => Some((dim, a, true)),
+                (None, Some(b)) => Some((dim, b, false)),
+                _ => None,
+            },
+        )
+    }
+
     /// Returns `true` if both vectors have at least one dimension in common
     #[inline]
     pub fn overlaps_with(&self, other: &Vector) -> bool {
@@ -93,6 +242,78 @@ impl Vector {
             .is_some()
     }
 
+    /// Returns the amount of dimensions both vectors have in common
+    #[inline]
+    pub fn overlap_count(&self, other: &Vector) -> usize {
+        if !self.could_overlap(other) {
+            return 0;
+        }
+
+        LockStepIter::new(self.inner.iter().copied(), other.inner.iter().copied()).count()
+    }
+
+    /// Returns the amount of dimensions which are only in one of both vectors
+    #[inline]
+    pub fn hamming_distance(&self, other: &Vector) -> usize {
+        self.dimen_count() + other.dimen_count() - 2 * self.overlap_count(other)
+    }
+
+    /// Returns the share of dimensions of `self` which are also in `other`. Other than the
+    /// similarity this is not symmetric. Returns `0.0` if `self` is empty
+    #[inline]
+    pub fn containment(&self, other: &Vector) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        self.overlap_count(other) as f32 / self.dimen_count() as f32
+    }
+
+    /// Returns the amount of shared dimensions divided by the dimension count of the smaller
+    /// vector. Other than the similarity this ignores the weights and lengths of both vectors, so
+    /// long documents containing all terms of a short query get the highest score. Returns `0.0`
+    /// if one of the vectors is empty
+    #[inline]
+    pub fn overlap_ratio(&self, other: &Vector) -> f32 {
+        let min = self.dimen_count().min(other.dimen_count());
+        if min == 0 {
+            return 0.0;
+        }
+
+        self.overlap_count(other) as f32 / min as f32
+    }
+
+    /// Sums up the smaller value of each dimension both vectors share and divides it by the
+    /// smaller sum of values of both vectors. This behaves like a weighted `containment` and is
+    /// less sensitive to different magnitudes than the cosine similarity. Returns `0.0` if the
+    /// sum of values of one vector is `0.0`
+    pub fn soft_overlap(&self, other: &Vector) -> f32 {
+        let l1_a: f32 = self.vec_values().map(f32::abs).sum();
+        let l1_b: f32 = other.vec_values().map(f32::abs).sum();
+        let norm = l1_a.min(l1_b);
+        if norm == 0.0 {
+            return 0.0;
+        }
+
+        let shared: f32 = self.overlapping(other).map(|(_, a, b)| a.min(b)).sum();
+        shared / norm
+    }
+
+    /// Calculates a MinHash signature of the vectors dimensions using `num_hashes` hash functions
+    /// derived from `seed`. The share of equal slots of two signatures approximates the Jaccard
+    /// similarity of their dimensions, see `minhash_similarity`. Weights are ignored
+    pub fn minhash(&self, num_hashes: usize, seed: u64) -> Vec<u32> {
+        (0..num_hashes as u64)
+            .map(|i| {
+                let fn_seed = splitmix64(seed.wrapping_add(i));
+                self.vec_indices()
+                    .map(|dim| (splitmix64(dim as u64 ^ fn_seed) >> 32) as u32)
+                    .min()
+                    .unwrap_or(u32::MAX)
+            })
+            .collect()
+    }
+
     /// Returns `true` if both vectors could potentionally have overlapping vectors
     #[inline]
     pub fn could_overlap(&self, other: &Vector) -> bool {
@@ -126,12 +347,42 @@ impl Vector {
         self.sort();
     }
 
+    /// Like `update` but returns an error if the vector contains a value which is infinite or NaN
+    /// since it would make the length and all similarities NaN as well
+    #[inline]
+    pub fn update_checked(&mut self) -> Result<(), Error> {
+        if self.vec_values().any(|i| !i.is_finite()) {
+            return Err(Error::NonFinite);
+        }
+
+        self.update();
+        Ok(())
+    }
+
+    /// Replaces all infinite and NaN values with `0.0` and updates the vector
+    #[inline]
+    pub fn sanitize(&mut self) {
+        for (_, val) in self.inner.iter_mut() {
+            if !val.is_finite() {
+                *val = 0.0;
+            }
+        }
+
+        self.update();
+    }
+
     /// Get the length of the vector
     #[inline(always)]
     pub fn get_length(&self) -> f32 {
         self.length
     }
 
+    /// Returns an iterator over all dimensions and their values in ascending order
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (u32, f32)> + '_ {
+        self.inner.iter().copied()
+    }
+
     /// Returns an iterator over all dimensions of the vector
     #[inline]
     pub fn vec_indices(&self) -> impl Iterator<Item = u32> + '_ {
@@ -144,19 +395,133 @@ impl Vector {
         self.inner.iter().map(|i| i.1)
     }
 
+    /// Returns an iterator over all dimensions in `lo..hi` and their values in ascending order. The
+    /// first dimension gets looked up using a binary search
+    #[inline]
+    pub fn dims_in_range(&self, lo: u32, hi: u32) -> impl Iterator<Item = (u32, f32)> + '_ {
+        let start = self.inner.partition_point(|i| i.0 < lo);
+        self.inner[start..]
+            .iter()
+            .copied()
+            .take_while(move |i| i.0 < hi)
+    }
+
+    /// Inserts a dimension without having to sort the whole vector again. `on_conflict` decides
+    /// what happens if the dimension already exists. The length does not get updated so `update`
+    /// has to be called after pushing all dimensions
+    pub fn push_dim(&mut self, dim: u32, value: f32, on_conflict: Conflict) {
+        match self.inner.binary_search_by(|a| a.0.cmp(&dim)) {
+            Ok(pos) => match on_conflict {
+                Conflict::Sum => self.inner[pos].1 += value,
+                Conflict::Replace => self.inner[pos].1 = value,
+                Conflict::Keep => (),
+                Conflict::Max => self.inner[pos].1 = self.inner[pos].1.max(value),
+            },
+            Err(pos) => self.inner.insert(pos, (dim, value)),
+        }
+    }
+
+    /// Sets the value of `dim` to `new_value`, inserting the dimension if it doesn't exist yet.
+    /// Other than calling `update` the length gets adjusted using the old and new value only.
+    /// It gets fully recalculated if this results in a length which isn't finite. Setting a
+    /// dimension which doesn't exist to `0.0` leaves the vector unchanged
+    pub fn update_dim(&mut self, dim: u32, new_value: f32) {
+        let old_value = match self.inner.binary_search_by(|a| a.0.cmp(&dim)) {
+            Ok(pos) => std::mem::replace(&mut self.inner[pos].1, new_value),
+            Err(_) if new_value == 0.0 => return,
+            Err(pos) => {
+                self.inner.insert(pos, (dim, new_value));
+                0.0
+            }
+        };
+
+        let length = (self.length.powi(2) - old_value.powi(2) + new_value.powi(2)).sqrt();
+        self.length = if length.is_finite() {
+            length
+        } else {
+            self.calc_len()
+        };
+    }
+
+    /// Multiplies the value of each dimension with its weight in `weights`, eg. one returned by
+    /// `Index::idf_vector`. Dimensions without a weight keep their value. The vector gets
+    /// sanitized afterwards
+    pub fn apply_weights(&mut self, weights: &HashMap<u32, f32>) {
+        for (dim, val) in self.inner.iter_mut() {
+            if let Some(weight) = weights.get(dim) {
+                *val *= weight;
+            }
+        }
+
+        self.sanitize();
+    }
+
+    /// Returns the highest value of the vector or `None` if the vector is empty
+    #[inline]
+    pub fn max_value(&self) -> Option<f32> {
+        self.vec_values().reduce(f32::max)
+    }
+
+    /// Returns the lowest value of the vector or `None` if the vector is empty
+    #[inline]
+    pub fn min_value(&self) -> Option<f32> {
+        self.vec_values().reduce(f32::min)
+    }
+
     /// Deletes a given dimension and its value from the vector
     #[inline]
     pub fn delete_dim(&mut self, dim: u32) {
         self.inner.retain(|(curr_dim, _)| *curr_dim != dim);
     }
 
+    /// Maps every dimension to a new one using `f`. Dimensions mapped to `None` get removed. If
+    /// multiple dimensions get mapped to the same one their values get summed up, like
+    /// `push_dim` does with `Conflict::Sum`. The vector gets sorted and updated afterwards
+    pub fn remap<F: Fn(u32) -> Option<u32>>(&mut self, f: F) {
+        let mut remapped: Vec<_> = self
+            .inner
+            .iter()
+            .filter_map(|(dim, val)| Some((f(*dim)?, *val)))
+            .collect();
+        remapped.sort_by_key(|i| i.0);
+
+        // Sum up collisions before calculating the length
+        remapped.dedup_by(|a, b| {
+            let collides = a.0 == b.0;
+            if collides {
+                b.1 += a.1;
+            }
+            collides
+        });
+
+        self.inner = remapped;
+        self.update();
+        self.shrink_to_fit();
+    }
+
+    /// Removes all dimensions for which `f` returns `false` and updates the vector. Memory which
+    /// isn't needed anymore gets released afterwards
+    pub fn retain<F: FnMut(u32, f32) -> bool>(&mut self, mut f: F) {
+        self.inner.retain(|(dim, val)| f(*dim, *val));
+        self.update();
+        self.shrink_to_fit();
+    }
+
+    /// Releases the capacity of the vector which isn't used by any dimension. This is worth it
+    /// after removing many dimensions of vectors kept in memory for a long time
+    #[inline]
+    pub fn shrink_to_fit(&mut self) {
+        self.inner.shrink_to_fit();
+    }
+
     #[inline]
     pub fn sparse_iter_mut(&mut self) -> IterMut<(u32, f32)> {
         self.inner.iter_mut()
     }
 
+    /// Calculates the scalar product of both vectors
     #[inline]
-    fn scalar(&self, other: &Vector) -> f32 {
+    pub(crate) fn scalar(&self, other: &Vector) -> f32 {
         LockStepIter::new(self.inner.iter().copied(), other.inner.iter().copied())
             .map(|(_, a, b)| a * b)
             .sum()
@@ -184,6 +549,139 @@ impl Vector {
             .sqrt()
     }
 
+    /// Encodes the vector storing each value as `u8` within `[0, max]` where `max` is the vectors
+    /// highest value. This reduces the size of each dimension from 7 to 4 bytes. Negative values
+    /// are stored as `0`
+    pub fn encode_quantized<T: ByteOrder>(&self) -> Result<Vec<u8>, Error> {
+        let mut encoded = Vec::with_capacity(6 + (self.inner.len() * 4));
+
+        let max = self.max_value().unwrap_or(0.0).max(0.0);
+
+        // 0..2 vector-dimension count
+        encoded.write_u16::<T>(self.inner.len() as u16)?;
+
+        // 2..6 value range
+        encoded.write_f32::<T>(max)?;
+
+        // n*u32..
+        for (dimension, value) in self.inner.iter() {
+            let quantized = if max > 0.0 {
+                (value.max(0.0) / max * u8::MAX as f32).round() as u8
+            } else {
+                0
+            };
+            encoded.write_u24::<T>(*dimension)?;
+            encoded.write_u8(quantized)?;
+        }
+
+        Ok(encoded)
+    }
+
+    /// Decodes a vector encoded with `encode_quantized`. The length gets recalculated from the
+    /// restored values
+    pub fn decode_quantized<T: ByteOrder, R: Read>(mut data: R) -> Result<Self, Error> {
+        // 0..2 vector-dimension count
+        let vector_dim_count = data.read_u16::<T>()?;
+
+        // 2..6 value range
+        let max = data.read_f32::<T>()?;
+
+        let dimensions: Vec<_> = (0..vector_dim_count)
+            .map(|_| -> Result<_, std::io::Error> {
+                let dim = data.read_u24::<T>()?;
+                let val = data.read_u8()? as f32 / u8::MAX as f32 * max;
+                Ok((dim, val))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let mut vec = Vector::new_raw(dimensions, 0.0);
+        vec.length = vec.calc_len();
+        Ok(vec)
+    }
+
+    /// Encodes the vector storing all dimensions followed by all values, allowing to read the
+    /// values as a contiguous slice. Dimensions are stored as full `u32` so both arrays are made of
+    /// 4 byte items, which takes one byte more per dimension than `encode`
+    pub fn encode_columnar<T: ByteOrder>(&self) -> Result<Vec<u8>, Error> {
+        let mut encoded = Vec::with_capacity(8 + (self.inner.len() * 8));
+
+        // 0..4 vector length
+        encoded.write_f32::<T>(self.length)?;
+
+        // 4..8 vector-dimension count
+        encoded.write_u32::<T>(self.inner.len() as u32)?;
+
+        // n*u32 dimensions
+        for (dimension, _) in self.inner.iter() {
+            encoded.write_u32::<T>(*dimension)?;
+        }
+
+        // n*f32 values
+        for (_, value) in self.inner.iter() {
+            encoded.write_f32::<T>(*value)?;
+        }
+
+        Ok(encoded)
+    }
+
+    /// Decodes a vector encoded with `encode_columnar`
+    pub fn decode_columnar<T: ByteOrder, R: Read>(mut data: R) -> Result<Self, Error> {
+        // 0..4 vector length
+        let vec_length = data.read_f32::<T>()?;
+
+        // 4..8 vector-dimension count
+        let vector_dim_count = data.read_u32::<T>()? as u64;
+
+        // Read both columns without trusting the count for the allocation
+        let mut columns = Vec::new();
+        (&mut data)
+            .take(vector_dim_count * 8)
+            .read_to_end(&mut columns)?;
+        if columns.len() as u64 != vector_dim_count * 8 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let (dims, values) = columns.split_at(columns.len() / 2);
+        let dimensions = dims
+            .chunks_exact(4)
+            .map(T::read_u32)
+            .zip(values.chunks_exact(4).map(T::read_f32))
+            .collect();
+
+        Ok(Vector::new_raw(dimensions, vec_length))
+    }
+
+    /// Calculates the scalar product of `other` and a vector encoded with `encode_columnar`
+    /// directly on the encoded columns. Only the values of dimensions `other` has too are read.
+    /// Returns the scalar product and the length of the encoded vector
+    pub(crate) fn scalar_columnar<T: ByteOrder>(
+        data: &[u8],
+        other: &Vector,
+    ) -> Result<(f32, f32), Error> {
+        if data.len() < 8 {
+            return Err(Error::Decode);
+        }
+
+        // 0..4 vector length
+        let vec_length = T::read_f32(&data[0..4]);
+
+        // 4..8 vector-dimension count
+        let vector_dim_count = T::read_u32(&data[4..8]) as usize;
+
+        let columns = &data[8..];
+        if columns.len() / 8 < vector_dim_count {
+            return Err(Error::Decode);
+        }
+        let (dims, values) = columns.split_at(vector_dim_count * 4);
+
+        let dims = dims.chunks_exact(4).map(T::read_u32).zip(0..);
+        let scalar = LockStepIter::new(dims, other.inner.iter().copied())
+            .map(|(_, pos, b)| T::read_f32(&values[pos * 4..]) * b)
+            .sum();
+
+        Ok((scalar, vec_length))
+    }
+
     /// Sort the Vec<> by the dimensions
     #[inline]
     fn sort(&mut self) {
@@ -213,3 +711,489 @@ impl Eq for Vector {
     #[inline]
     fn assert_receiver_is_total_eq(&self) {}
 }
+
+impl Encodable for Vector {
+    fn encode<T: ByteOrder>(&self) -> Result<Vec<u8>, Error> {
+        let mut encoded = Vec::with_capacity(6 + (self.inner.len() * 6));
+
+        // 0..4 vector length
+        encoded.write_f32::<T>(self.length)?;
+
+        // 4..6 vector-dimension count
+        encoded.write_u16::<T>(self.inner.len() as u16)?;
+
+        // n*u48..
+        for (dimension, value) in self.inner.iter() {
+            encoded.write_u24::<T>(*dimension)?;
+            encoded.write_f32::<T>(*value)?;
+        }
+
+        Ok(encoded)
+    }
+}
+
+impl Decodable for Vector {
+    #[inline]
+    fn decode<T: ByteOrder, R: Read>(mut data: R) -> Result<Self, Error> {
+        // 0..4 vector length
+        let vec_length = data.read_f32::<T>()?;
+
+        // 4..6 vector-dimension count
+        let vector_dim_count = data.read_u16::<T>()?;
+
+        let dimensions: Vec<_> = (0..vector_dim_count)
+            .map(|_| -> Result<_, std::io::Error> {
+                let dim = data.read_u24::<T>()?;
+                let val = data.read_f32::<T>()?;
+                Ok((dim, val))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Vector::new_raw(dimensions, vec_length))
+    }
+}
+
+/// A pool of reusable vector buffers. Allows building many short living vectors, eg. queries,
+/// without allocating new buffers for each of them
+#[derive(Debug, Default)]
+pub struct VectorPool {
+    buffers: Vec<Vec<(u32, f32)>>,
+}
+
+impl VectorPool {
+    /// Creates a new empty pool
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns an empty buffer, reusing a released one if available
+    #[inline]
+    pub fn acquire(&mut self) -> Vec<(u32, f32)> {
+        self.buffers.pop().unwrap_or_default()
+    }
+
+    /// Gives the buffer of `vec` back to the pool so it can be reused
+    #[inline]
+    pub fn release(&mut self, vec: Vector) {
+        let mut buf = vec.inner;
+        buf.clear();
+        self.buffers.push(buf);
+    }
+
+    /// Returns the amount of buffers available for reuse
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Returns `true` if there is no buffer available for reuse
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+}
+
+/// Returns the share of equal slots of two MinHash signatures created with `Vector::minhash`
+/// using the same amount of hash functions and seed. Returns `0.0` for empty signatures
+pub fn minhash_similarity(sig_a: &[u32], sig_b: &[u32]) -> f32 {
+    let len = sig_a.len().min(sig_b.len());
+    if len == 0 {
+        return 0.0;
+    }
+
+    let equal = sig_a.iter().zip(sig_b).filter(|(a, b)| a == b).count();
+    equal as f32 / len as f32
+}
+
+/// Mixes the bits of `x`. Used as hash function for MinHash signatures
+#[inline]
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_symmetric_difference() {
+        let a = Vector::create_new_raw(vec![(1, 1.0), (3, 2.0), (5, 3.0)]);
+        let b = Vector::create_new_raw(vec![(3, 4.0), (4, 5.0), (5, 6.0), (9, 7.0)]);
+
+        let diff: Vec<_> = a.symmetric_difference(&b).collect();
+        assert_eq!(diff, vec![(1, 1.0, true), (4, 5.0, false), (9, 7.0, false)]);
+
+        let diff: Vec<_> = b.symmetric_difference(&a).collect();
+        assert_eq!(diff, vec![(1, 1.0, false), (4, 5.0, true), (9, 7.0, true)]);
+    }
+
+    #[test]
+    fn test_hamming_distance() {
+        let a = Vector::create_new_raw(vec![(1, 1.0), (3, 2.0), (5, 3.0)]);
+        let b = Vector::create_new_raw(vec![(2, 1.0), (4, 1.0)]);
+        let c = Vector::create_new_raw(vec![(3, 1.0), (5, 1.0), (7, 1.0), (8, 1.0)]);
+
+        assert_eq!(a.hamming_distance(&a), 0);
+        assert_eq!(a.hamming_distance(&b), 5);
+        assert_eq!(a.hamming_distance(&c), 3);
+        assert_eq!(c.hamming_distance(&a), 3);
+        assert_eq!(a.hamming_distance(&Vector::new_empty()), 3);
+    }
+
+    #[test]
+    fn test_containment() {
+        let query = Vector::create_new_raw(vec![(2, 1.0), (4, 1.0)]);
+        let doc = Vector::create_new_raw(vec![(1, 1.0), (2, 1.0), (3, 1.0), (4, 1.0)]);
+
+        assert_eq!(query.containment(&doc), 1.0);
+        assert_eq!(doc.containment(&query), 0.5);
+        assert_eq!(Vector::new_empty().containment(&doc), 0.0);
+    }
+
+    #[test]
+    fn test_push_dim() {
+        let base = Vector::create_new_raw(vec![(1, 1.0), (5, 2.0)]);
+
+        let mut sum = base.clone();
+        sum.push_dim(3, 4.0, Conflict::Sum);
+        sum.push_dim(5, 1.0, Conflict::Sum);
+        sum.update();
+        assert_eq!(sum.sparse_vec(), &vec![(1, 1.0), (3, 4.0), (5, 3.0)]);
+        assert_eq!(sum.get_length(), (1.0f32 + 16.0 + 9.0).sqrt());
+
+        let mut replace = base.clone();
+        replace.push_dim(5, 7.0, Conflict::Replace);
+        replace.push_dim(0, 1.0, Conflict::Replace);
+        assert_eq!(replace.sparse_vec(), &vec![(0, 1.0), (1, 1.0), (5, 7.0)]);
+
+        let mut keep = base.clone();
+        keep.push_dim(5, 7.0, Conflict::Keep);
+        keep.push_dim(9, 1.0, Conflict::Keep);
+        assert_eq!(keep.sparse_vec(), &vec![(1, 1.0), (5, 2.0), (9, 1.0)]);
+
+        let mut max = base;
+        max.push_dim(1, 0.5, Conflict::Max);
+        max.push_dim(5, 3.0, Conflict::Max);
+        assert_eq!(max.sparse_vec(), &vec![(1, 1.0), (5, 3.0)]);
+    }
+
+    #[test]
+    fn test_non_finite_values() {
+        let mut vec = Vector::create_new_raw(vec![(1, 1.0), (2, 2.0)]);
+        vec.sparse_vec_mut().push((3, f32::INFINITY));
+
+        assert!(matches!(vec.update_checked(), Err(Error::NonFinite)));
+
+        vec.sanitize();
+        assert_eq!(vec.get_dim(3), Some(0.0));
+        assert_eq!(vec.get_length(), 5.0f32.sqrt());
+        assert!(vec.update_checked().is_ok());
+
+        // Arithmetic methods sanitize their results
+        let mut weights = HashMap::new();
+        weights.insert(2, f32::INFINITY);
+        vec.apply_weights(&weights);
+        assert_eq!(vec.get_dim(2), Some(0.0));
+        assert_eq!(vec.get_length(), 1.0);
+
+        let blended = Vector::blend(&[(Vector::create_new_raw(vec![(1, 1.0)]), f32::INFINITY)]);
+        assert_eq!(blended.get_dim(1), Some(0.0));
+        assert_eq!(blended.get_length(), 0.0);
+    }
+
+    #[test]
+    fn test_iter() {
+        let vec = Vector::create_new_raw(vec![(9, 1.0), (2, 2.0), (5, 3.0)]);
+        let items: Vec<_> = vec.iter().collect();
+        assert_eq!(items, vec![(2, 2.0), (5, 3.0), (9, 1.0)]);
+    }
+
+    #[test]
+    fn test_min_max_value() {
+        let vec = Vector::create_new_raw(vec![(1, 0.5), (2, 3.0), (7, -1.5), (9, 2.0)]);
+        assert_eq!(vec.max_value(), Some(3.0));
+        assert_eq!(vec.min_value(), Some(-1.5));
+
+        assert_eq!(Vector::new_empty().max_value(), None);
+        assert_eq!(Vector::new_empty().min_value(), None);
+    }
+
+    #[test]
+    fn test_cosine_many() {
+        let query = Vector::create_new_raw(vec![(1, 1.0), (3, 2.0)]);
+        let others = vec![
+            Vector::create_new_raw(vec![(1, 1.0), (3, 2.0)]),
+            Vector::create_new_raw(vec![(1, 0.5), (2, 4.0)]),
+            Vector::create_new_raw(vec![(7, 1.0)]),
+        ];
+
+        let mut out = vec![42.0];
+        query.cosine_many(&others, &mut out);
+
+        assert_eq!(out.len(), others.len());
+        for (score, other) in out.iter().zip(others.iter()) {
+            assert!((score - query.similarity(other)).abs() < 1e-6);
+        }
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn test_base64() {
+        let vec = Vector::create_new_raw(vec![(1, 0.5), (70000, 2.0), (3, -1.25)]);
+        let decoded = Vector::from_base64(&vec.to_base64()).unwrap();
+
+        assert_eq!(decoded.sparse_vec(), vec.sparse_vec());
+        assert_eq!(decoded.get_length(), vec.get_length());
+        assert!(Vector::from_base64("not base64!").is_err());
+    }
+
+    #[test]
+    fn test_remap() {
+        let mut vec = Vector::create_new_raw(vec![(1, 1.0), (2, 2.0), (5, 3.0), (7, 4.0)]);
+
+        // Reverse the order of the dimensions and drop dimension 5
+        vec.remap(|dim| (dim != 5).then(|| 10 - dim));
+
+        let dims: Vec<_> = vec.iter().collect();
+        assert_eq!(dims, vec![(3, 4.0), (8, 2.0), (9, 1.0)]);
+        assert_eq!(vec.get_length(), Vector::create_new_raw(dims).get_length());
+
+        // Values of colliding dimensions get summed up
+        let mut vec = Vector::create_new_raw(vec![(1, 3.0), (2, 4.0), (3, 1.0)]);
+        vec.remap(|dim| Some(dim / 2));
+        assert_eq!(vec.iter().collect::<Vec<_>>(), vec![(0, 3.0), (1, 5.0)]);
+        assert_eq!(vec.get_length(), 34f32.sqrt());
+
+        // NaN values are kept
+        let mut vec = Vector::create_new_raw(vec![(1, f32::NAN), (2, 4.0)]);
+        vec.remap(Some);
+        assert!(vec.get_dim(1).unwrap().is_nan());
+        assert!(vec.get_length().is_nan());
+    }
+
+    #[test]
+    fn test_overlap_ratio() {
+        let query = Vector::create_new_raw(vec![(2, 1.0), (4, 1.0)]);
+        let long = Vector::create_new_raw((0..20).map(|i| (i, 1.0)).collect());
+        let partial = Vector::create_new_raw(vec![(2, 1.0), (3, 1.0)]);
+
+        assert_eq!(query.overlap_ratio(&long), 1.0);
+        assert_eq!(long.overlap_ratio(&query), 1.0);
+        assert!(query.similarity(&long) < query.similarity(&partial));
+        assert!(query.overlap_ratio(&long) > query.overlap_ratio(&partial));
+
+        assert_eq!(query.overlap_ratio(&Vector::new_empty()), 0.0);
+    }
+
+    #[test]
+    fn test_from_term_counts() {
+        use crate::build::{weights::DefaultTFIDF, IndexBuilder};
+
+        let mut builder = IndexBuilder::new();
+        builder.insert_new_vec(0u32, &["a", "b"]);
+        builder.insert_new_vec(1u32, &["b", "c"]);
+        builder.insert_new_vec(2u32, &["c", "d"]);
+        let index = builder.build(()).unwrap();
+        let indexer = index.get_indexer();
+
+        let counts = [("a", 3), ("b", 1), ("unknown", 2), ("d", 0)];
+        let vec = Vector::from_term_counts(indexer, &counts, &DefaultTFIDF, 3).unwrap();
+
+        let a = indexer.index_of("a").unwrap();
+        let b = indexer.index_of("b").unwrap();
+        assert_eq!(vec.dimen_count(), 2);
+        assert_eq!(vec.get_dim(a), Some(DefaultTFIDF.weight(3.0, 3, 1, 3)));
+        assert_eq!(vec.get_dim(b), Some(DefaultTFIDF.weight(1.0, 1, 2, 3)));
+
+        assert!(Vector::from_term_counts(indexer, &[("unknown", 1)], &DefaultTFIDF, 3).is_none());
+    }
+
+    #[test]
+    fn test_minhash() {
+        let base = Vector::create_new_raw((0..100).map(|i| (i, 1.0)).collect());
+        let similar = Vector::create_new_raw((5..105).map(|i| (i, 2.0)).collect());
+        let other = Vector::create_new_raw((200..300).map(|i| (i, 1.0)).collect());
+
+        let sig = base.minhash(128, 42);
+        assert_eq!(sig.len(), 128);
+        assert_eq!(sig, base.minhash(128, 42));
+
+        let sim_similar = minhash_similarity(&sig, &similar.minhash(128, 42));
+        let sim_other = minhash_similarity(&sig, &other.minhash(128, 42));
+        assert!(sim_similar > 0.7);
+        assert!(sim_similar > sim_other);
+        assert_eq!(minhash_similarity(&sig, &sig), 1.0);
+        assert_eq!(minhash_similarity(&[], &sig), 0.0);
+    }
+
+    #[test]
+    fn test_encode_columnar() {
+        use byteorder::LittleEndian;
+
+        let vec = Vector::create_new_raw(vec![(1, 0.5), (4, 2.0), (70000, 1.25)]);
+        let enc = vec.encode_columnar::<LittleEndian>().unwrap();
+        assert_eq!(enc.len(), 8 + 3 * 8);
+
+        let dec = Vector::decode_columnar::<LittleEndian, _>(&enc[..]).unwrap();
+        assert_eq!(
+            dec.iter().collect::<Vec<_>>(),
+            vec.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(dec.get_length(), vec.get_length());
+
+        // Values are stored contiguously after the dimensions
+        assert_eq!(LittleEndian::read_f32(&enc[8 + 3 * 4..]), 0.5);
+        assert!(Vector::decode_columnar::<LittleEndian, _>(&enc[..enc.len() - 1]).is_err());
+
+        // A count exceeding the data must not be trusted
+        let mut huge = enc.clone();
+        LittleEndian::write_u32(&mut huge[4..8], u32::MAX);
+        assert!(Vector::decode_columnar::<LittleEndian, _>(&huge[..]).is_err());
+        assert!(Vector::scalar_columnar::<LittleEndian>(&huge, &vec).is_err());
+
+        let query = Vector::create_new_raw(vec![(0, 3.0), (4, 1.5), (70000, 2.0)]);
+        let (scalar, length) = Vector::scalar_columnar::<LittleEndian>(&enc, &query).unwrap();
+        assert_eq!(scalar, vec.scalar(&query));
+        assert_eq!(length, vec.get_length());
+    }
+
+    #[test]
+    fn test_vector_pool() {
+        let mut pool = VectorPool::new();
+        assert!(pool.is_empty());
+
+        let pairs: Vec<_> = (0..100).rev().map(|i| (i, 1.0)).collect();
+        let vec = Vector::from_pool(&mut pool, &pairs);
+        assert_eq!(vec, Vector::create_new_raw(pairs));
+        let capacity = vec.inner.capacity();
+        let ptr = vec.inner.as_ptr();
+
+        pool.release(vec);
+        assert_eq!(pool.len(), 1);
+
+        let vec = Vector::from_pool(&mut pool, &[(3, 1.0), (1, 2.0)]);
+        assert!(pool.is_empty());
+        assert_eq!(vec.inner.capacity(), capacity);
+        assert_eq!(vec.inner.as_ptr(), ptr);
+        assert_eq!(vec.iter().collect::<Vec<_>>(), vec![(1, 2.0), (3, 1.0)]);
+    }
+
+    #[test]
+    fn test_similarity_on() {
+        let a = Vector::create_new_raw(vec![(1, 1.0), (2, 3.0), (5, 1.0)]);
+        let b = Vector::create_new_raw(vec![(1, 1.0), (2, 1.0), (7, 2.0)]);
+
+        let all: HashSet<u32> = [1, 2, 5, 7].iter().copied().collect();
+        assert!((a.similarity_on(&b, &all) - a.similarity(&b)).abs() < 0.0001);
+
+        let masked: HashSet<u32> = [1, 2].iter().copied().collect();
+        let sim = a.similarity_on(&b, &masked);
+        assert!((sim - 4.0 / (10.0f32.sqrt() * 2.0f32.sqrt())).abs() < 0.0001);
+        assert!(sim > a.similarity(&b));
+
+        let disjoint: HashSet<u32> = [5, 7].iter().copied().collect();
+        assert_eq!(a.similarity_on(&b, &disjoint), 0.0);
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut vec = Vector::create_new_raw((0..1000).map(|i| (i, 1.0)).collect());
+        assert!(vec.sparse_vec().capacity() >= 1000);
+
+        vec.retain(|dim, _| dim % 100 == 0);
+        assert_eq!(vec.dimen_count(), 10);
+        assert!(vec.sparse_vec().capacity() < 1000);
+        assert_eq!(vec.get_length(), 10.0f32.sqrt());
+    }
+
+    #[test]
+    fn test_dims_in_range() {
+        let vec = Vector::create_new_raw(vec![(1, 1.0), (4, 2.0), (5, 3.0), (9, 4.0), (12, 5.0)]);
+
+        let range: Vec<_> = vec.dims_in_range(4, 12).collect();
+        assert_eq!(range, vec![(4, 2.0), (5, 3.0), (9, 4.0)]);
+
+        let range: Vec<_> = vec.dims_in_range(2, 5).collect();
+        assert_eq!(range, vec![(4, 2.0)]);
+
+        assert_eq!(vec.dims_in_range(0, 100).count(), 5);
+        assert_eq!(vec.dims_in_range(6, 9).count(), 0);
+        assert_eq!(vec.dims_in_range(13, 20).count(), 0);
+        assert_eq!(vec.dims_in_range(9, 4).count(), 0);
+    }
+
+    #[test]
+    fn test_update_dim() {
+        let mut vec = Vector::create_new_raw(vec![(1, 1.0), (4, 2.0), (5, 3.0)]);
+        vec.update_dim(4, 5.0);
+        vec.update_dim(2, 0.5);
+        vec.update_dim(5, 0.0);
+
+        let mut full = vec.clone();
+        full.update();
+        assert!((vec.get_length() - full.get_length()).abs() < 0.0001);
+        assert_eq!(vec.sparse_vec(), full.sparse_vec());
+        assert_eq!(vec.get_dim(2), Some(0.5));
+
+        // Squaring large values overflows, which requires recalculating the length
+        let mut vec = Vector::create_new_raw(vec![(1, 1e20)]);
+        vec.update_dim(1, 0.0);
+        assert_eq!(vec.get_length(), 0.0);
+
+        // Zero values aren't inserted
+        vec.update_dim(3, 0.0);
+        assert_eq!(vec.sparse_vec(), &vec![(1, 0.0)]);
+
+        // Non finite values are kept like `update` does
+        vec.update_dim(3, f32::NAN);
+        assert!(vec.get_dim(3).unwrap().is_nan());
+        assert!(vec.get_length().is_nan());
+        vec.update_dim(3, 2.0);
+        assert_eq!(vec.get_length(), 2.0);
+    }
+
+    #[test]
+    fn test_apply_weights() {
+        let mut vec = Vector::create_new_raw(vec![(1, 1.0), (4, 2.0), (5, 3.0)]);
+        let idf: HashMap<u32, f32> = [(1, 2.0), (5, 0.0), (9, 4.0)].iter().copied().collect();
+        vec.apply_weights(&idf);
+
+        assert_eq!(vec.sparse_vec(), &vec![(1, 2.0), (4, 2.0), (5, 0.0)]);
+        assert_eq!(vec.get_length(), 8.0f32.sqrt());
+    }
+
+    #[test]
+    fn test_blend() {
+        let a = Vector::create_new_raw(vec![(1, 1.0), (3, 2.0)]);
+        let b = Vector::create_new_raw(vec![(2, 1.0), (3, 1.0)]);
+
+        let blended = Vector::blend(&[(a, 2.0), (b, 0.5)]);
+        assert_eq!(blended.sparse_vec(), &vec![(1, 2.0), (2, 0.5), (3, 4.5)]);
+        assert_eq!(blended.get_length(), (4.0f32 + 0.25 + 20.25).sqrt());
+
+        assert!(Vector::blend(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_soft_overlap() {
+        let a = Vector::create_new_raw(vec![(1, 1.0), (2, 1.0), (3, 1.0)]);
+        let scaled = Vector::create_new_raw(vec![(1, 10.0), (2, 10.0), (3, 10.0)]);
+        let skewed = Vector::create_new_raw(vec![(1, 1.0), (2, 1.0), (3, 8.0)]);
+
+        // Cosine ignores uniform scaling but is sensitive to a single large value
+        assert!((a.similarity(&scaled) - 1.0).abs() < 1e-6);
+        assert!(a.similarity(&skewed) < 0.8);
+
+        // Every value of `a` is covered by both other vectors
+        assert!((a.soft_overlap(&scaled) - 1.0).abs() < 1e-6);
+        assert!((a.soft_overlap(&skewed) - 1.0).abs() < 1e-6);
+        assert_eq!(a.soft_overlap(&Vector::new_empty()), 0.0);
+
+        let partial = Vector::create_new_raw(vec![(1, 0.5), (4, 1.5)]);
+        assert!((a.soft_overlap(&partial) - 0.25).abs() < 1e-6);
+    }
+}
@@ -1,16 +1,22 @@
 use crate::{
     document::DocumentVector,
     error::Error,
-    inv_index::{DimToVecs, InvertedIndex, NewDimVecMap},
+    index::top_k,
+    inv_index::{DimToVecs, InvertedIndex, LegacyInvertedIndex, NewDimVecMap},
     traits::{Decodable, Encodable},
+    vector::Conflict,
     Vector,
 };
-use byteorder::LittleEndian;
+use byteorder::{LittleEndian, ReadBytesExt};
 use indexed_file::mem_file::MemFile;
 use itertools::Itertools;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+    convert::TryFrom,
+    io::Read,
     marker::PhantomData,
     ops::{Deref, DerefMut},
 };
@@ -18,9 +24,63 @@ use std::{
 /// A struct containing raw data of vectors and a map from a dimension to a set of those vectors.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VectorStore<D> {
-    store: MemFile,
-    map: InvertedIndex,
+    pub(crate) store: MemFile,
+    pub(crate) map: InvertedIndex,
     vec_type: PhantomData<D>,
+    /// Amount of terms (including repeated ones) of each document. Empty for indexes written
+    /// before document lengths were stored
+    #[serde(default)]
+    pub(crate) lengths: Vec<u32>,
+    /// The format the vectors are stored in
+    #[serde(default)]
+    pub(crate) encoding: VectorEncoding,
+    /// External IDs of the vectors assigned with `IndexBuilder::insert_with_id`. Empty if no
+    /// vector has an external ID
+    #[serde(default)]
+    pub(crate) external_ids: Vec<Option<u64>>,
+    /// Amount of identical documents each vector represents if they got collapsed using
+    /// `IndexBuilder::with_dedup`. Empty if no documents were collapsed
+    #[serde(default)]
+    pub(crate) multiplicities: Vec<u32>,
+    /// Frequencies of the terms within each document as (dimension, frequency) pairs sorted by
+    /// the dimension. Only stored if built with `IndexBuilder::with_term_freqs`
+    #[serde(default)]
+    pub(crate) term_freqs: Vec<Vec<(u32, u32)>>,
+}
+
+/// Format of the vectors stored in a `VectorStore`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum VectorEncoding {
+    /// Dimensions and values are stored in pairs using `DocumentVector::encode`
+    Packed = 0u8,
+    /// Values are stored using 8 bits only. See `DocumentVector::encode_quantized`
+    Quantized = 1u8,
+    /// All dimensions are stored before all values. This takes one more byte per dimension than
+    /// `Packed` but allows reading the values as contiguous slice. See
+    /// `DocumentVector::encode_columnar`
+    Columnar = 2u8,
+}
+
+impl Default for VectorEncoding {
+    #[inline]
+    fn default() -> Self {
+        Self::Packed
+    }
+}
+
+impl TryFrom<u8> for VectorEncoding {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::Packed,
+            1 => Self::Quantized,
+            2 => Self::Columnar,
+            _ => return Err(Error::Decode),
+        })
+    }
 }
 
 impl<D> VectorStore<D> {
@@ -41,6 +101,53 @@ impl<D> VectorStore<D> {
         &self.map
     }
 
+    /// Returns the amount of bytes of all encoded vectors in the store
+    #[inline]
+    pub fn store_byte_len(&self) -> usize {
+        self.store.iter().map(|i| i.len()).sum()
+    }
+
+    /// Returns the amount of bytes used by the inverted index
+    #[inline]
+    pub fn index_byte_len(&self) -> usize {
+        self.map.byte_len()
+    }
+
+    /// Returns `true` if the vectors weights are stored quantized to 8 bits
+    #[inline]
+    pub fn is_quantized(&self) -> bool {
+        self.encoding == VectorEncoding::Quantized
+    }
+
+    /// Returns the format the vectors are stored in
+    #[inline]
+    pub fn encoding(&self) -> VectorEncoding {
+        self.encoding
+    }
+
+    /// Returns the amount of terms, including repeated ones, the document with the given ID had
+    /// when building the index. Returns `None` for indexes written before document lengths were
+    /// stored
+    #[inline]
+    pub fn doc_length(&self, id: u32) -> Option<u32> {
+        self.lengths.get(id as usize).copied()
+    }
+
+    /// Returns the amount of identical documents the vector with the ID `id` represents. This is
+    /// always `1` unless the index was built using `IndexBuilder::with_dedup`
+    #[inline]
+    pub fn multiplicity(&self, id: u32) -> u32 {
+        self.multiplicities.get(id as usize).copied().unwrap_or(1)
+    }
+
+    /// Returns the frequencies of the terms within the document with the ID `id` as
+    /// (dimension, frequency) pairs. Returns `None` if the index wasn't built using
+    /// `IndexBuilder::with_term_freqs`
+    #[inline]
+    pub fn term_freqs(&self, id: u32) -> Option<&[(u32, u32)]> {
+        self.term_freqs.get(id as usize).map(|i| i.as_slice())
+    }
+
     /// Return the size of the given dimension. The size represents the amount of vectors which are
     /// laying in the dimension.
     #[inline]
@@ -82,12 +189,37 @@ impl<D> VectorStore<D> {
         self.get_in_dims_iter(dimensions.iter().copied())
     }
 
+    /// Creates a new `VectorStore` from encoded vectors and their inverted index
+    #[inline]
+    pub(crate) fn from_parts(
+        store: MemFile,
+        map: InvertedIndex,
+        lengths: Vec<u32>,
+        encoding: VectorEncoding,
+    ) -> Self {
+        Self {
+            store,
+            map,
+            vec_type: PhantomData,
+            lengths,
+            encoding,
+            external_ids: vec![],
+            multiplicities: vec![],
+            term_freqs: vec![],
+        }
+    }
+
     #[inline]
     pub(crate) fn clone_full(&self) -> Self {
         Self {
             store: self.store.clone(),
             map: self.map.clone(),
             vec_type: self.vec_type,
+            lengths: self.lengths.clone(),
+            encoding: self.encoding,
+            external_ids: self.external_ids.clone(),
+            multiplicities: self.multiplicities.clone(),
+            term_freqs: self.term_freqs.clone(),
         }
     }
 }
@@ -98,7 +230,141 @@ impl<D: Decodable> VectorStore<D> {
     pub fn iter(&self) -> impl Iterator<Item = DocumentVector<D>> + '_ {
         self.store
             .iter()
-            .map(|i| Self::decode_vec(i).expect("Invalid index format"))
+            .map(move |i| self.decode_vec(i).expect("Invalid index format"))
+    }
+
+    /// Returns an iterator over all Vectors in the vecstore. Other than `iter` vectors which can't
+    /// be decoded are returned as error instead of panicking
+    #[inline]
+    pub fn iter_checked(&self) -> impl Iterator<Item = Result<DocumentVector<D>, Error>> + '_ {
+        self.store.iter().map(move |i| self.try_decode_vec(i))
+    }
+
+    /// Returns an iterator over all Vectors in the vecstore together with their IDs
+    #[inline]
+    pub fn iter_with_ids(&self) -> impl Iterator<Item = (u32, DocumentVector<D>)> + '_ {
+        self.store.iter().enumerate().map(move |(id, i)| {
+            let vec = self.decode_vec(i).expect("Invalid index format");
+            (id as u32, vec)
+        })
+    }
+
+    /// Folds all vectors of the store into a single value without loading them all at once
+    #[inline]
+    pub fn fold<A, F>(&self, init: A, mut f: F) -> A
+    where
+        F: FnMut(A, &DocumentVector<D>) -> A,
+    {
+        self.iter().fold(init, |acc, vec| f(acc, &vec))
+    }
+
+    /// Returns the `n` dimensions with the highest sum of values over all vectors, together with
+    /// their sum in descending order
+    pub fn top_weighted_dims(&self, n: usize) -> Vec<(u32, f32)> {
+        let totals = self.fold(HashMap::<u32, f32>::new(), |mut totals, vec| {
+            for (dim, val) in vec.vector().iter() {
+                *totals.entry(dim).or_default() += val;
+            }
+            totals
+        });
+
+        let mut totals: Vec<_> = totals.into_iter().collect();
+        totals.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
+        totals.truncate(n);
+        totals
+    }
+
+    /// Returns the IDs of the `n` vectors with the most dimensions together with their dimension
+    /// count in descending order. Vectors with the same count are ordered by their ID
+    pub fn docs_by_dimension_count(&self, n: usize) -> Vec<(u32, usize)> {
+        let mut heap = BinaryHeap::with_capacity(n + 1);
+
+        for (id, vec) in self.iter_with_ids() {
+            heap.push(Reverse((vec.vector().dimen_count(), Reverse(id))));
+            if heap.len() > n {
+                heap.pop();
+            }
+        }
+
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((count, Reverse(id)))| (id, count))
+            .collect()
+    }
+
+    /// Calculates the weighted mean of the vectors referenced in `items` using their assigned
+    /// weight. Returns `None` if none of the vectors exist or the total weight is 0
+    pub fn weighted_centroid(&self, items: &[(u32, f32)]) -> Option<Vector> {
+        let mut centroid = Vector::new_empty();
+        let mut total_weight = 0.0;
+
+        for (id, weight) in items {
+            let vec = match self.load_vector(*id as usize) {
+                Some(vec) => vec,
+                None => continue,
+            };
+
+            for (dim, val) in vec.vector().iter() {
+                centroid.push_dim(dim, val * weight, Conflict::Sum);
+            }
+            total_weight += weight;
+        }
+
+        if total_weight == 0.0 {
+            return None;
+        }
+
+        for (_, val) in centroid.sparse_iter_mut() {
+            *val /= total_weight;
+        }
+        centroid.update();
+
+        Some(centroid)
+    }
+
+    /// Returns `n` randomly picked vectors using reservoir sampling. The same `seed` always results
+    /// in the same sample. Returns all vectors if there are less than `n`
+    pub fn sample(&self, n: usize, seed: u64) -> Vec<(u32, DocumentVector<D>)> {
+        if n == 0 {
+            return vec![];
+        }
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut reservoir = Vec::with_capacity(n.min(self.len()));
+
+        for (pos, item) in self.iter_with_ids().enumerate() {
+            if pos < n {
+                reservoir.push(item);
+                continue;
+            }
+
+            let replace = rng.gen_range(0..=pos);
+            if replace < n {
+                reservoir[replace] = item;
+            }
+        }
+
+        reservoir
+    }
+
+    /// Builds the inverted index again from all vectors in the store. This has to be called after
+    /// vectors got modified to keep the mapping from dimensions to vectors correct
+    pub fn rebuild_index(&mut self) {
+        let mut dim_vec_map: DimToVecs = HashMap::new();
+
+        for (id, vec) in self.iter_with_ids() {
+            for dim in vec.vector().vec_indices() {
+                dim_vec_map.entry(dim).or_default().push(id);
+            }
+        }
+
+        self.map = NewDimVecMap::new(dim_vec_map)
+            .with_gap_threshold(self.map.gap_threshold())
+            .build();
     }
 
     /// Returns all vectors in `dimension`
@@ -144,6 +410,117 @@ impl<D: Decodable> VectorStore<D> {
         self.load_documents_iter(self.get_in_dims_iter2(q_vec.vec_indices()))
     }
 
+    /// Like `get_for_vec` but only returns vectors sharing at least `min_overlap` dimensions with
+    /// `q_vec`. The overlap is counted using the inverted index, so vectors below the threshold
+    /// never get decoded
+    pub fn get_for_vec_filtered(
+        &self,
+        q_vec: &Vector,
+        min_overlap: usize,
+    ) -> impl Iterator<Item = DocumentVector<D>> + '_ {
+        let mut overlap: HashMap<u32, usize> = HashMap::new();
+        for dim in q_vec.vec_indices() {
+            for id in self.get_map().get(dim).unwrap_or_default() {
+                *overlap.entry(id).or_default() += 1;
+            }
+        }
+
+        let mut ids: Vec<_> = overlap
+            .into_iter()
+            .filter(|(_, count)| *count >= min_overlap)
+            .map(|(id, _)| id)
+            .collect();
+        ids.sort_unstable();
+
+        self.load_documents_iter(ids.into_iter())
+    }
+
+    /// Returns the IDs of the `k` vectors most similar to `q_vec` together with their similarity
+    /// in descending order
+    pub fn get_for_vec_scored(&self, q_vec: &Vector, k: usize) -> Vec<(u32, f32)> {
+        if k == 0 || self.is_empty() || q_vec.is_empty() || q_vec.get_length() == 0.0 {
+            return vec![];
+        }
+
+        let candidates = self.get_in_dims_iter(q_vec.vec_indices());
+        top_k(self.score_all(q_vec, candidates.into_iter()), k)
+    }
+
+    /// Returns all pairs of vector IDs sharing at least `min_shared` dimensions together with the
+    /// amount of shared dimensions, ordered by their IDs. The lower ID of a pair always comes
+    /// first. Pairs are found using the inverted index, so vectors without any shared dimension
+    /// are never returned
+    pub fn overlap_edges(&self, min_shared: usize) -> Vec<(u32, u32, usize)> {
+        let mut shared: HashMap<(u32, u32), usize> = HashMap::new();
+
+        for (_, ids) in self.get_map().iter_postings() {
+            for (pos, a) in ids.iter().enumerate() {
+                for b in &ids[pos + 1..] {
+                    *shared.entry((*a, *b)).or_default() += 1;
+                }
+            }
+        }
+
+        let mut edges: Vec<_> = shared
+            .into_iter()
+            .filter(|(_, count)| *count >= min_shared)
+            .map(|((a, b), count)| (a, b, count))
+            .collect();
+        edges.sort_unstable();
+        edges
+    }
+
+    /// Calculates the shannon entropy of the values of `dim` across all vectors using it, after
+    /// normalizing them to sum up to `1.0`. A low entropy means the weight of the dimension is
+    /// concentrated in few vectors. Returns `0.0` if no vector uses the dimension
+    pub fn dimension_entropy(&self, dim: u32) -> f32 {
+        let values: Vec<f32> = self
+            .get_in_dims_iter(std::iter::once(dim))
+            .into_iter()
+            .filter_map(|id| self.load_vector(id as usize)?.vector().get_dim(dim))
+            .map(f32::abs)
+            .collect();
+
+        let sum: f32 = values.iter().sum();
+        if sum == 0.0 {
+            return 0.0;
+        }
+
+        values
+            .into_iter()
+            .filter(|val| *val > 0.0)
+            .map(|val| {
+                let p = val / sum;
+                -p * p.ln()
+            })
+            .sum()
+    }
+
+    /// Returns the similarity of `query` to each of the vectors referenced by `ids`. The length
+    /// of the query gets calculated only once for all vectors. IDs not in the store get skipped.
+    /// Columnar vectors are scored on their encoded columns without decoding the document
+    #[inline]
+    pub fn score_all<'a>(
+        &'a self,
+        query: &'a Vector,
+        ids: impl Iterator<Item = u32> + 'a,
+    ) -> impl Iterator<Item = (u32, f32)> + 'a {
+        let q_inv_len = 1.0 / query.get_length();
+        ids.filter_map(move |id| {
+            if self.encoding == VectorEncoding::Columnar {
+                let data = self.store.get(id as usize)?;
+                let (sc, length) = Vector::scalar_columnar::<LittleEndian>(data, query).ok()?;
+                if sc == 0.0 {
+                    return Some((id, 0.0));
+                }
+                return Some((id, sc * (1.0 / length) * q_inv_len));
+            }
+
+            let vec = self.load_vector(id as usize)?;
+            Some((id, vec.score_against(query, q_inv_len)))
+        })
+    }
+
     /// Load all documents by their ids
     #[inline]
     pub fn load_documents_iter<'a>(
@@ -165,12 +542,17 @@ impl<D: Decodable> VectorStore<D> {
     /// Read and decode a vector from `self.store` and returns it
     #[inline]
     pub fn load_vector(&self, id: usize) -> Option<DocumentVector<D>> {
-        Self::decode_vec(self.store.get(id)?)
+        self.decode_vec(self.store.get(id)?)
+    }
+
+    #[inline]
+    fn decode_vec(&self, data: &[u8]) -> Option<DocumentVector<D>> {
+        self.try_decode_vec(data).ok()
     }
 
     #[inline]
-    fn decode_vec(data: &[u8]) -> Option<DocumentVector<D>> {
-        DocumentVector::<D>::decode::<LittleEndian, _>(data).ok()
+    fn try_decode_vec(&self, data: &[u8]) -> Result<DocumentVector<D>, Error> {
+        decode_vec(data, self.encoding)
     }
 }
 
@@ -222,17 +604,46 @@ impl<'a, D: Decodable + Encodable> AsRef<Vector> for VecMod<'a, D> {
 impl<'a, D: Encodable + Decodable> Drop for VecMod<'a, D> {
     #[inline]
     fn drop(&mut self) {
-        let enc = self
-            .vec
-            .encode::<LittleEndian>()
-            .expect("Failed to encode vec again");
+        let enc = encode_vec(&self.vec, self.store.encoding).expect("Failed to encode vec again");
         self.store.store.replace(self.v_id as usize, &enc);
     }
 }
 
-/// Creates a new DocumentStore using a with `build` generated DocumentStore.
+/// Encodes a vector using the given `encoding`
+#[inline]
+fn encode_vec<D: Encodable>(
+    vec: &DocumentVector<D>,
+    encoding: VectorEncoding,
+) -> Result<Vec<u8>, Error> {
+    match encoding {
+        VectorEncoding::Packed => vec.encode::<LittleEndian>(),
+        VectorEncoding::Quantized => vec.encode_quantized::<LittleEndian>(),
+        VectorEncoding::Columnar => vec.encode_columnar::<LittleEndian>(),
+    }
+}
+
+/// Decodes a vector encoded with `encode_vec` using the given `encoding`
+#[inline]
+fn decode_vec<D: Decodable>(
+    data: &[u8],
+    encoding: VectorEncoding,
+) -> Result<DocumentVector<D>, Error> {
+    match encoding {
+        VectorEncoding::Packed => DocumentVector::decode::<LittleEndian, _>(data),
+        VectorEncoding::Quantized => DocumentVector::decode_quantized::<LittleEndian, _>(data),
+        VectorEncoding::Columnar => DocumentVector::decode_columnar::<LittleEndian, _>(data),
+    }
+}
+
+/// Creates a new DocumentStore using a with `build` generated DocumentStore. `lengths` contains
+/// the amount of terms of each document and may be empty if unknown. `encoding` defines the
+/// format the vectors get stored in. `gap_threshold` is the largest gap between two dimensions
+/// which gets padded in the inverted index
 pub(crate) fn build<D: Encodable + Decodable>(
     vectors: Vec<DocumentVector<D>>,
+    lengths: Vec<u32>,
+    encoding: VectorEncoding,
+    gap_threshold: u32,
 ) -> Result<VectorStore<D>, Error> {
     //let mut encoded_vectors: Vec<u8> = Vec::new();
     let mut index = MemFile::with_capacity(vectors.len());
@@ -241,7 +652,7 @@ pub(crate) fn build<D: Encodable + Decodable>(
     let mut dim_vec_map: DimToVecs = HashMap::new();
 
     for vector in vectors {
-        let vec_enc = vector.encode::<LittleEndian>()?;
+        let vec_enc = encode_vec(&vector, encoding)?;
         let vec_id = index.insert(&vec_enc);
 
         // Bulid map from dimension to all vectors in this dimension
@@ -254,15 +665,105 @@ pub(crate) fn build<D: Encodable + Decodable>(
         v.sort_unstable();
     }
 
-    let map = NewDimVecMap::new(dim_vec_map).build();
+    let map = NewDimVecMap::new(dim_vec_map)
+        .with_gap_threshold(gap_threshold)
+        .build();
 
     Ok(VectorStore {
         store: index,
         map,
         vec_type: PhantomData,
+        lengths,
+        encoding,
+        external_ids: vec![],
+        multiplicities: vec![],
+        term_freqs: vec![],
     })
 }
 
+/// Decodes the vectors of an encoded vector store one at a time from `reader`. The data has to
+/// follow the layout of an encoded `MemFile`, so vectors never have to be loaded into memory at
+/// once. `encoding` has to be the one of the vector store, see `VectorStore::encoding`.
+/// Iteration stops after the first error.
+pub fn stream_decode<D: Decodable, R: Read>(
+    reader: R,
+    encoding: VectorEncoding,
+) -> impl Iterator<Item = Result<DocumentVector<D>, Error>> {
+    StreamDecoder {
+        reader,
+        remaining: None,
+        buf: Vec::new(),
+        encoding,
+        vec_type: PhantomData,
+    }
+}
+
+/// Iterator over vectors decoded from a raw stream. See `stream_decode`
+struct StreamDecoder<D, R> {
+    reader: R,
+    /// Amount of vectors left. `None` if the header hasn't been read yet
+    remaining: Option<usize>,
+    buf: Vec<u8>,
+    encoding: VectorEncoding,
+    vec_type: PhantomData<D>,
+}
+
+impl<D: Decodable, R: Read> StreamDecoder<D, R> {
+    fn read_next(&mut self) -> Result<Option<DocumentVector<D>>, Error> {
+        let remaining = match self.remaining {
+            Some(r) => r,
+            None => self.reader.read_u32::<LittleEndian>()? as usize,
+        };
+
+        if remaining == 0 {
+            self.remaining = Some(0);
+            return Ok(None);
+        }
+        self.remaining = Some(remaining - 1);
+
+        let len = self.reader.read_u32::<LittleEndian>()? as usize;
+        self.buf.resize(len, 0);
+        self.reader.read_exact(&mut self.buf)?;
+
+        Ok(Some(decode_vec(&self.buf, self.encoding)?))
+    }
+}
+
+impl<D: Decodable, R: Read> Iterator for StreamDecoder<D, R> {
+    type Item = Result<DocumentVector<D>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_next() {
+            Ok(v) => v.map(Ok),
+            Err(err) => {
+                // Don't continue reading from a stream in an unknown state
+                self.remaining = Some(0);
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Layout of a `VectorStore` serialized before document lengths were stored
+#[derive(Deserialize)]
+pub(crate) struct LegacyVectorStore<D> {
+    store: MemFile,
+    map: LegacyInvertedIndex,
+    vec_type: PhantomData<D>,
+}
+
+impl<D> From<LegacyVectorStore<D>> for VectorStore<D> {
+    #[inline]
+    fn from(legacy: LegacyVectorStore<D>) -> Self {
+        Self::from_parts(
+            legacy.store,
+            legacy.map.into(),
+            vec![],
+            VectorEncoding::Packed,
+        )
+    }
+}
+
 impl<D: Decodable> Default for VectorStore<D> {
     #[inline]
     fn default() -> Self {
@@ -270,6 +771,298 @@ impl<D: Decodable> Default for VectorStore<D> {
             store: Default::default(),
             map: Default::default(),
             vec_type: Default::default(),
+            lengths: Default::default(),
+            encoding: VectorEncoding::default(),
+            external_ids: Default::default(),
+            multiplicities: Default::default(),
+            term_freqs: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::inv_index::DEFAULT_GAP_THRESHOLD;
+
+    fn test_store() -> VectorStore<u32> {
+        let vectors = vec![
+            DocumentVector::new(0, Vector::create_new_raw(vec![(0, 1.0), (1, 1.0)])),
+            DocumentVector::new(1, Vector::create_new_raw(vec![(1, 1.0), (2, 1.0)])),
+            DocumentVector::new(2, Vector::create_new_raw(vec![(2, 1.0), (3, 1.0)])),
+        ];
+        build(
+            vectors,
+            vec![],
+            VectorEncoding::Packed,
+            DEFAULT_GAP_THRESHOLD,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_get_for_vec_filtered() {
+        let store = test_store();
+        let query = Vector::create_new_raw(vec![(1, 1.0), (2, 1.0)]);
+
+        let docs = |min_overlap| -> Vec<_> {
+            store
+                .get_for_vec_filtered(&query, min_overlap)
+                .map(|i| i.document)
+                .collect()
+        };
+        assert_eq!(docs(1), vec![0, 1, 2]);
+        assert_eq!(docs(2), vec![1]);
+        assert!(docs(3).is_empty());
+    }
+
+    #[test]
+    fn test_get_for_vec_scored() {
+        let store = test_store();
+        let query = Vector::create_new_raw(vec![(1, 1.0), (2, 2.0)]);
+
+        let res = store.get_for_vec_scored(&query, 2);
+        let ids: Vec<_> = res.iter().map(|i| i.0).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert!(res[0].1 > res[1].1);
+
+        assert_eq!(store.get_for_vec_scored(&query, 10).len(), 3);
+        assert!(store.get_for_vec_scored(&query, 0).is_empty());
+        assert!(store.get_for_vec_scored(&Vector::new_empty(), 2).is_empty());
+    }
+
+    #[test]
+    fn test_overlap_edges() {
+        let vectors = vec![
+            DocumentVector::new(
+                0u32,
+                Vector::create_new_raw(vec![(0, 1.0), (1, 1.0), (2, 1.0)]),
+            ),
+            DocumentVector::new(1, Vector::create_new_raw(vec![(1, 1.0), (2, 1.0)])),
+            DocumentVector::new(2, Vector::create_new_raw(vec![(2, 1.0), (3, 1.0)])),
+            DocumentVector::new(3, Vector::create_new_raw(vec![(4, 1.0)])),
+        ];
+        let store = build(
+            vectors,
+            vec![],
+            VectorEncoding::Packed,
+            DEFAULT_GAP_THRESHOLD,
+        )
+        .unwrap();
+
+        let edges = store.overlap_edges(1);
+        assert_eq!(edges, vec![(0, 1, 2), (0, 2, 1), (1, 2, 1)]);
+        assert_eq!(store.overlap_edges(2), vec![(0, 1, 2)]);
+        assert!(store.overlap_edges(3).is_empty());
+    }
+
+    #[test]
+    fn test_dimension_entropy() {
+        let vectors = vec![
+            DocumentVector::new(0u32, Vector::create_new_raw(vec![(0, 1.0), (1, 10.0)])),
+            DocumentVector::new(1, Vector::create_new_raw(vec![(0, 1.0), (1, 0.1)])),
+            DocumentVector::new(2, Vector::create_new_raw(vec![(0, 1.0), (1, 0.1)])),
+        ];
+        let store = build(
+            vectors,
+            vec![],
+            VectorEncoding::Packed,
+            DEFAULT_GAP_THRESHOLD,
+        )
+        .unwrap();
+
+        let uniform = store.dimension_entropy(0);
+        let dominated = store.dimension_entropy(1);
+        assert!((uniform - 3.0f32.ln()).abs() < 0.0001);
+        assert!(dominated < uniform);
+        assert_eq!(store.dimension_entropy(5), 0.0);
+    }
+
+    #[test]
+    fn test_score_all() {
+        let store = test_store();
+        let query = Vector::create_new_raw(vec![(1, 1.0), (2, 1.0)]);
+
+        let scores: Vec<_> = store
+            .score_all(&query, vec![0, 1, 2, 10].into_iter())
+            .collect();
+        assert_eq!(scores.len(), 3);
+        for (id, score) in scores {
+            let expected = store
+                .load_vector(id as usize)
+                .unwrap()
+                .vector()
+                .similarity(&query);
+            assert!((score - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_sample() {
+        let store = test_store();
+
+        let ids = |sample: Vec<(u32, DocumentVector<u32>)>| -> Vec<u32> {
+            sample.into_iter().map(|i| i.0).collect()
+        };
+
+        let first = ids(store.sample(2, 1337));
+        assert_eq!(first.len(), 2);
+        assert_eq!(first, ids(store.sample(2, 1337)));
+
+        assert_eq!(ids(store.sample(10, 1337)), vec![0, 1, 2]);
+        assert!(store.sample(0, 1337).is_empty());
+    }
+
+    #[test]
+    fn test_fold() {
+        let store = test_store();
+        let dim_count = store.fold(0, |acc, vec| acc + vec.vector().dimen_count());
+        assert_eq!(dim_count, 6);
+    }
+
+    #[test]
+    fn test_rebuild_index() {
+        let mut store = test_store();
+
+        {
+            let mut vec = store.mod_vector(0).unwrap();
+            vec.delete_dim(0);
+            vec.push_dim(5, 1.0, Conflict::Replace);
+            vec.update();
         }
+        store.rebuild_index();
+
+        let fresh = build(
+            store.iter().collect(),
+            vec![],
+            VectorEncoding::Packed,
+            DEFAULT_GAP_THRESHOLD,
+        )
+        .unwrap();
+        assert_eq!(
+            store.get_map().decoded_btree(),
+            fresh.get_map().decoded_btree()
+        );
+        assert_eq!(store.get_in_dim(5).unwrap()[0].document, 0);
+    }
+
+    #[test]
+    fn test_top_weighted_dims() {
+        let vectors = vec![
+            DocumentVector::new(0, Vector::create_new_raw(vec![(0, 1.0), (1, 5.0)])),
+            DocumentVector::new(1, Vector::create_new_raw(vec![(0, 1.0), (2, 2.5)])),
+            DocumentVector::new(2, Vector::create_new_raw(vec![(0, 1.0), (2, 2.0)])),
+        ];
+        let store: VectorStore<u32> = build(
+            vectors,
+            vec![],
+            VectorEncoding::Packed,
+            DEFAULT_GAP_THRESHOLD,
+        )
+        .unwrap();
+
+        assert_eq!(store.top_weighted_dims(2), vec![(1, 5.0), (2, 4.5)]);
+        assert_eq!(store.top_weighted_dims(10).len(), 3);
+    }
+
+    #[test]
+    fn test_weighted_centroid() {
+        let store = test_store();
+        let doc0 = store.load_vector(0).unwrap();
+        let doc1 = store.load_vector(1).unwrap();
+
+        let centroid = store.weighted_centroid(&[(0, 3.0), (1, 1.0)]).unwrap();
+        assert_eq!(centroid.get_dim(0), Some(0.75));
+        assert_eq!(centroid.get_dim(1), Some(1.0));
+        assert_eq!(centroid.get_dim(2), Some(0.25));
+        assert!(centroid.similarity(doc0.vector()) > centroid.similarity(doc1.vector()));
+
+        assert!(store.weighted_centroid(&[]).is_none());
+        assert!(store.weighted_centroid(&[(10, 1.0)]).is_none());
+    }
+
+    #[test]
+    fn test_stream_decode() {
+        let store = test_store();
+        let encoded = store.store.encode::<LittleEndian>().unwrap();
+
+        let decoded = stream_decode::<u32, _>(&encoded[..], VectorEncoding::Packed)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(decoded, store.iter().collect::<Vec<_>>());
+
+        // Other encodings are decoded using the given one
+        for encoding in [VectorEncoding::Quantized, VectorEncoding::Columnar] {
+            let other = build(
+                store.iter().collect(),
+                vec![],
+                encoding,
+                DEFAULT_GAP_THRESHOLD,
+            )
+            .unwrap();
+            let encoded = other.store.encode::<LittleEndian>().unwrap();
+            let decoded = stream_decode::<u32, _>(&encoded[..], encoding)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(decoded, other.iter().collect::<Vec<_>>());
+        }
+
+        // Truncated input yields an error and stops
+        let mut iter =
+            stream_decode::<u32, _>(&encoded[..encoded.len() - 2], VectorEncoding::Packed);
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_docs_by_dimension_count() {
+        let vectors = vec![
+            DocumentVector::new(0, Vector::create_new_raw(vec![(0, 1.0)])),
+            DocumentVector::new(
+                1,
+                Vector::create_new_raw(vec![(0, 1.0), (1, 1.0), (2, 1.0)]),
+            ),
+            DocumentVector::new(2, Vector::create_new_raw(vec![(1, 1.0), (2, 1.0)])),
+            DocumentVector::new(3, Vector::create_new_raw(vec![(3, 1.0), (4, 1.0)])),
+        ];
+        let store: VectorStore<u32> = build(
+            vectors,
+            vec![],
+            VectorEncoding::Packed,
+            DEFAULT_GAP_THRESHOLD,
+        )
+        .unwrap();
+
+        assert_eq!(
+            store.docs_by_dimension_count(3),
+            vec![(1, 3), (2, 2), (3, 2)]
+        );
+        assert_eq!(store.docs_by_dimension_count(10).len(), 4);
+        assert!(store.docs_by_dimension_count(0).is_empty());
+    }
+
+    #[test]
+    fn test_byte_len() {
+        let store = test_store();
+        assert!(store.store_byte_len() > 0);
+        assert!(store.index_byte_len() > 0);
+
+        // Length, dimension count, 2 dimensions and the document for each vector
+        assert_eq!(store.store_byte_len(), 3 * (4 + 2 + 2 * 7 + 4));
+        assert_eq!(VectorStore::<u32>::default().store_byte_len(), 0);
+    }
+
+    #[test]
+    fn test_iter_checked() {
+        let mut store = test_store();
+        store.store.replace(1, &[1, 2]);
+
+        let res: Vec<_> = store.iter_checked().collect();
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].as_ref().unwrap().document, 0);
+        assert!(res[1].is_err());
+        assert_eq!(res[2].as_ref().unwrap().document, 2);
     }
 }